@@ -2,12 +2,15 @@
 
 use anyhow::Result;
 use env_logger::init;
+use pigame::graphics::backend::{TerminalBackend, VncBackend};
+use pigame::graphics::camera::{reset_camera, set_camera, Camera2D};
+use pigame::graphics::layer::draw_on_layer;
 use pigame::graphics::text::{draw_text_ex, load_ttf_font};
 use pigame::graphics::{
     clear_background, draw_rectangle, get_frame_time, get_time, next_frame, screen_height,
-    screen_width,
+    screen_width, set_display_backend,
 };
-use pigame::input::is_active;
+use pigame::input::{is_active, is_quit_requested};
 use pigame::{
     graphics::{
         colour::{BLACK, BLUE, GREEN, ORANGE, RED, WHITE, YELLOW},
@@ -20,11 +23,13 @@ use pigame::{
     },
     rand::{random, thread_rng, Rng},
 };
+use std::env::args;
 
 const PLAYER_SIZE: Vec2 = Vec2::from_array([53., 12.]);
 const PLAYER_SPEED: f32 = 700.;
 const BLOCK_SIZE: Vec2 = Vec2::from_array([53., 12.]);
 const BALL_SIZE: f32 = 7.;
+const HUD_LAYER: i32 = 1;
 
 pub enum GameState {
     Menu,
@@ -69,12 +74,12 @@ impl Player {
     }
 
     pub fn draw(&self) {
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        #[allow(clippy::cast_possible_truncation)]
         draw_rectangle(
-            self.rect.x as u32,
-            self.rect.y as u32,
-            self.rect.w as u32,
-            self.rect.h as u32,
+            self.rect.x as i32,
+            self.rect.y as i32,
+            self.rect.w as i32,
+            self.rect.h as i32,
             BLUE,
         );
     }
@@ -96,12 +101,12 @@ impl Block {
     }
 
     pub fn draw(&self) {
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        #[allow(clippy::cast_possible_truncation)]
         draw_rectangle(
-            self.rect.x as u32,
-            self.rect.y as u32,
-            self.rect.w as u32,
-            self.rect.h as u32,
+            self.rect.x as i32,
+            self.rect.y as i32,
+            self.rect.w as i32,
+            self.rect.h as i32,
             match self.row {
                 0 | 1 => RED,
                 2 | 3 => ORANGE,
@@ -160,12 +165,12 @@ impl Ball {
     }
 
     pub fn draw(&self) {
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        #[allow(clippy::cast_possible_truncation)]
         draw_rectangle(
-            self.rect.x as u32,
-            self.rect.y as u32,
-            self.rect.w as u32,
-            self.rect.h as u32,
+            self.rect.x as i32,
+            self.rect.y as i32,
+            self.rect.w as i32,
+            self.rect.h as i32,
             WHITE,
         );
     }
@@ -223,6 +228,11 @@ fn init_blocks(blocks: &mut Vec<Block>) {
 #[allow(clippy::too_many_lines)]
 fn main() -> Result<()> {
     init();
+    if args().any(|arg| arg == "--terminal") {
+        set_display_backend(TerminalBackend::new(1280, 720)?)?;
+    } else if args().any(|arg| arg == "--vnc") {
+        set_display_backend(VncBackend::new(1280, 720, "127.0.0.1:5900")?)?;
+    }
     let font = load_ttf_font("res/Quinque Five Font.ttf", FontSettings::default())?;
     let mut score = 0;
     let mut hits = 0;
@@ -234,10 +244,15 @@ fn main() -> Result<()> {
     let mut ball_spawned = false;
     let mut ball = Ball::new(vec2(player.rect.x, screen_height() as f32 / 2.));
     let mut ball_speed: i32 = 300;
+    let mut camera = Camera2D::default();
 
     init_blocks(&mut blocks);
 
     loop {
+        if is_quit_requested() {
+            return Ok(());
+        }
+
         if !player.dead {
             player.update()?;
         }
@@ -253,11 +268,13 @@ fn main() -> Result<()> {
         if ball.hit_lower_wall && !already_hit_lower_wall {
             player_lives += 1;
             already_hit_lower_wall = true;
+            camera.add_trauma(0.8);
             ball_spawned = false;
         }
 
         if resolve_collision(&mut ball.rect, &mut ball.vel, &player.rect) {
             hits += 1;
+            #[allow(clippy::collapsible_match)]
             match hits {
                 4 => {
                     if ball_speed < 375 {
@@ -306,6 +323,24 @@ fn main() -> Result<()> {
             }
         }
         clear_background(BLACK);
+        reset_camera();
+        if player.dead {
+            player.rect.y = screen_height() as f32 + 10.;
+        }
+        let score_text = format!("{score:0>#3}");
+        let lives_text = format!("{player_lives}");
+        #[allow(clippy::cast_possible_truncation)]
+        let show_score = player.dead || (get_time() * 6.) as i32 % 2 == 0;
+        let lives_x = i32::try_from(screen_width())? - 60;
+        draw_on_layer(HUD_LAYER, move || {
+            if show_score {
+                draw_text_ex(&score_text, 60, 40, font, 50000., WHITE);
+            }
+            draw_text_ex(&lives_text, lives_x, 40, font, 50000., WHITE);
+        });
+
+        camera.update();
+        set_camera(&camera);
         if !player.dead {
             blocks.retain(|block| block.lives > 0);
             player.draw();
@@ -325,17 +360,6 @@ fn main() -> Result<()> {
             ball.draw();
         }
 
-        let score_text = format!("{score:0>#3}");
-        let lives_text = format!("{player_lives}");
-        #[allow(clippy::cast_possible_truncation)]
-        if ((get_time() * 6.) as i32 % 2 == 0) && !player.dead {
-            draw_text_ex(&score_text, 60, 40, font, 50000., WHITE);
-        }
-        if player.dead {
-            player.rect.y = screen_height() as f32 + 10.;
-            draw_text_ex(&score_text, 60, 40, font, 50000., WHITE);
-        }
-        draw_text_ex(&lives_text, screen_width() - 60, 40, font, 50000., WHITE);
         next_frame()?;
     }
 }
@@ -4,13 +4,21 @@ use fontdue::Font;
 use once_cell::sync::Lazy;
 
 use crate::{
-    error::Result,
-    graphics::FrameBuffer,
+    error::{Error, Result},
+    graphics::{
+        backend::{DisplayBackend, FbdevBackend},
+        recording::{Recorder, RecordingHotkey},
+        FrameBuffer,
+    },
 };
 
-#[allow(clippy::unwrap_used)]
+static mut BACKEND: Option<Box<dyn DisplayBackend>> = None;
+
+#[allow(clippy::unwrap_used, clippy::non_std_lazy_statics)]
 static mut CONTEXT: Lazy<Context> = Lazy::new(|| {
-    Context::new()
+    #[allow(static_mut_refs)]
+    let backend = unsafe { BACKEND.take() };
+    Context::new(backend)
         .inspect_err(|error| eprintln!("{error}"))
         .unwrap()
 });
@@ -20,15 +28,23 @@ pub(crate) struct Context {
     pub(crate) start_time: Instant,
     pub(crate) fonts: Vec<Font>,
     pub(crate) last_frame: Instant,
+    pub(crate) recorder: Option<Recorder>,
+    pub(crate) recording_hotkey: Option<RecordingHotkey>,
 }
 
 impl Context {
-    pub(crate) fn new() -> Result<Self> {
+    pub(crate) fn new(backend: Option<Box<dyn DisplayBackend>>) -> Result<Self> {
+        let backend = match backend {
+            Some(backend) => backend,
+            None => Box::new(FbdevBackend::new()?),
+        };
         Ok(Self {
-            frame_buffer: FrameBuffer::new()?,
+            frame_buffer: FrameBuffer::new(backend)?,
             start_time: Instant::now(),
             fonts: Vec::new(),
             last_frame: Instant::now(),
+            recorder: None,
+            recording_hotkey: None,
         })
     }
 }
@@ -40,3 +56,23 @@ pub(crate) fn get() -> &'static mut Context {
         &mut CONTEXT
     }
 }
+
+/// Get the context if it has already been created, without creating it.
+#[must_use]
+pub(crate) fn try_get() -> Option<&'static mut Context> {
+    #[allow(static_mut_refs)]
+    unsafe {
+        Lazy::get_mut(&mut CONTEXT)
+    }
+}
+
+pub(crate) fn set_backend(backend: Box<dyn DisplayBackend>) -> Result<()> {
+    #[allow(static_mut_refs)]
+    unsafe {
+        if Lazy::get(&CONTEXT).is_some() {
+            return Err(Error::AlreadyInitialised);
+        }
+        BACKEND = Some(backend);
+    }
+    Ok(())
+}
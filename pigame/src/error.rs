@@ -4,6 +4,8 @@ use libc::strerror;
 use rppal::gpio;
 use thiserror::Error;
 
+use crate::graphics::backend::PixelFormat;
+
 /// Main error type.
 #[derive(Error, Debug)]
 pub enum Error {
@@ -24,6 +26,37 @@ pub enum Error {
     /// Error while loading font.
     #[error("error while loading font: {0}")]
     Font(&'static str),
+    /// The display uses a pixel format pigame cannot draw into.
+    #[error("unsupported pixel format: {0:?}")]
+    UnsupportedPixelFormat(PixelFormat),
+    /// The display was already initialised.
+    #[error("the display was already initialised")]
+    AlreadyInitialised,
+    /// Error while encoding a PNG image.
+    #[error("error while encoding png: {0}")]
+    PngEncoding(#[from] png::EncodingError),
+    /// Error while encoding a GIF image.
+    #[error("error while encoding gif: {0}")]
+    GifEncoding(#[from] gif::EncodingError),
+    /// The screen is too large to record as a GIF, which is at most 65535 pixels across and
+    /// down.
+    #[error("screen too large to record as gif")]
+    RecordingTooLarge,
+    /// Error while decoding a PNG image.
+    #[error("error while decoding png: {0}")]
+    PngDecoding(#[from] png::DecodingError),
+    /// An image could not be decoded.
+    #[error("invalid image: {0}")]
+    InvalidImage(&'static str),
+    /// Error while parsing a JSON file.
+    #[error("error while parsing json: {0}")]
+    Json(#[from] serde_json::Error),
+    /// Error while parsing an XML file.
+    #[error("error while parsing xml: {0}")]
+    Xml(#[from] roxmltree::Error),
+    /// A tile map could not be loaded.
+    #[error("invalid tile map: {0}")]
+    InvalidMap(&'static str),
     /// Error from the `rppal` crate.
     #[error("error from rppal: {0}")]
     Rppal(#[from] gpio::Error),
@@ -1,171 +1,318 @@
+/// Display backends and the trait they implement.
+pub mod backend;
+/// Cameras for scrolling, zooming and shaking the view of a world.
+pub mod camera;
+/// Offscreen images to draw into.
+pub mod canvas;
 /// Colour abstractions and functions.
 pub mod colour;
+/// Gradients blending between colours across rectangles and the screen.
+pub mod gradient;
+/// Drawing put off until the end of the frame and sorted into layers.
+pub mod layer;
+/// Resizable framed boxes drawn from an image cut into nine pieces.
+pub mod nine_slice;
+/// Indexed colour, with pixels picking from a palette of 256 colours.
+pub mod palette;
+/// Recording gameplay to animated images.
+pub mod recording;
+/// Screenshots of the frame being drawn.
+pub mod screenshot;
+/// Line, circle, ellipse and polygon drawing functions.
+pub mod shapes;
+/// Sprite sheets and frame animation.
+pub mod sprite;
 /// Text rendering functions.
 pub mod text;
+/// Images loaded from files and drawn as sprites.
+pub mod texture;
+/// Tile maps drawn from tileset images, and maps made with Tiled.
+pub mod tilemap;
 
-use crate::context::get;
+mod scale;
+/// Shared set up for tests that draw on the screen.
+#[cfg(test)]
+mod testing;
+mod transform;
+
+use crate::context::{get, set_backend};
 use crate::error::{Error, Result};
-use libc::__errno_location;
-use libc::ioctl;
 use log::info;
-use memmap::MmapOptions;
-use std::fs::File;
-use std::mem::zeroed;
 use std::time::{Duration, Instant};
 
-use memmap::MmapMut;
-use std::fs::OpenOptions;
-use std::os::fd::AsRawFd;
-
-use self::colour::Colour;
+use self::backend::{DisplayBackend, Region};
+use self::canvas::Canvas;
+use self::colour::{BlendMode, Colour, BLACK};
+use self::layer::Deferred;
 
 pub(crate) struct FrameBuffer {
-    pub(crate) file: File,
-    pub(crate) buffer: Vec<u8>,
-    pub(crate) map: MmapMut,
-    pub(crate) fixed_info: FixScreeninfo,
-    pub(crate) variable_info: VarScreeninfo,
-}
-
-#[repr(u64)]
-pub(crate) enum IoctlRequest {
-    FbiogetVscreeninfo = 0x4600,
-    FbiogetFscreeninfo = 0x4602,
-    FbioWaitforvsync = 0x4004_4620,
-}
-
-#[repr(C)]
-#[derive(Clone, Debug)]
-pub(crate) struct Bitfield {
-    pub(crate) offset: u32,
-    pub(crate) length: u32,
-    pub(crate) msb_right: u32,
-}
-
-#[repr(C)]
-#[derive(Clone, Debug)]
-pub(crate) struct VarScreeninfo {
-    pub(crate) xres: u32,
-    pub(crate) yres: u32,
-    pub(crate) xres_virtual: u32,
-    pub(crate) yres_virtual: u32,
-    pub(crate) xoffset: u32,
-    pub(crate) yoffset: u32,
-    pub(crate) bits_per_pixel: u32,
-    pub(crate) grayscale: u32,
-    pub(crate) red: Bitfield,
-    pub(crate) green: Bitfield,
-    pub(crate) blue: Bitfield,
-    pub(crate) transp: Bitfield,
-    pub(crate) nonstd: u32,
-    pub(crate) activate: u32,
-    pub(crate) height: u32,
-    pub(crate) width: u32,
-    pub(crate) accel_flags: u32,
-    pub(crate) pixclock: u32,
-    pub(crate) left_margin: u32,
-    pub(crate) right_margin: u32,
-    pub(crate) upper_margin: u32,
-    pub(crate) lower_margin: u32,
-    pub(crate) hsync_len: u32,
-    pub(crate) vsync_len: u32,
-    pub(crate) sync: u32,
-    pub(crate) vmode: u32,
-    pub(crate) rotate: u32,
-    pub(crate) colorspace: u32,
-    pub(crate) reserved: [u32; 4],
-}
-
-#[repr(C)]
-#[derive(Debug)]
-pub(crate) struct FixScreeninfo {
-    pub(crate) id: [u8; 16],
-    pub(crate) smem_start: usize,
-    pub(crate) smem_len: u32,
-    pub(crate) r#type: u32,
-    pub(crate) type_aux: u32,
-    pub(crate) visual: u32,
-    pub(crate) xpanstep: u16,
-    pub(crate) ypanstep: u16,
-    pub(crate) ywrapstep: u16,
-    pub(crate) line_length: u32,
-    pub(crate) mmio_start: usize,
-    pub(crate) mmio_len: u32,
-    pub(crate) accel: u32,
-    pub(crate) capabilities: u16,
-    pub(crate) reserved: [u16; 2],
+    pub(crate) backend: Box<dyn DisplayBackend>,
+    /// Back buffer everything is drawn into, at the logical resolution.
+    pub(crate) canvas: Canvas,
+    /// Resolution requested with [`set_logical_resolution`], if any.
+    pub(crate) logical_resolution: Option<(u32, u32)>,
+    /// Back buffer converted from palette indices into the pixel format of the display, when
+    /// drawing in indexed colour.
+    pub(crate) converted: Vec<u8>,
+    /// Frame the back buffer is scaled into when the logical resolution differs from the display.
+    pub(crate) scaled: Vec<u8>,
+    /// Frame rotated and flipped into the orientation of the display.
+    pub(crate) oriented: Vec<u8>,
+    pub(crate) border: Colour,
+    pub(crate) rotation: Rotation,
+    /// Whether frames are mirrored horizontally and vertically.
+    pub(crate) flip: (bool, bool),
+    /// Drawing put off until the end of the frame with [`layer::draw_on_layer`].
+    pub(crate) deferred: Vec<Deferred>,
 }
 
 impl FrameBuffer {
-    pub(crate) fn new() -> Result<Self> {
-        info!("opening framebuffer device");
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open("/dev/fb0")?;
-        info!("getting framebuffer information (fixed)");
-        let mut fixed_info: FixScreeninfo = unsafe { zeroed() };
-        if unsafe {
-            ioctl(
-                file.as_raw_fd(),
-                IoctlRequest::FbiogetFscreeninfo as _,
-                &mut fixed_info,
-            )
-        } == -1
-        {
-            return Err(Error::Ioctl(unsafe { *__errno_location() }));
-        };
-        info!("getting framebuffer information (variable)");
-        let mut variable_info: VarScreeninfo = unsafe { zeroed() };
-        if unsafe {
-            ioctl(
-                file.as_raw_fd(),
-                IoctlRequest::FbiogetVscreeninfo as _,
-                &mut variable_info,
-            )
-        } == -1
-        {
-            return Err(Error::Ioctl(unsafe { *__errno_location() }));
+    pub(crate) fn new(backend: Box<dyn DisplayBackend>) -> Result<Self> {
+        let pixel_format = backend.pixel_format();
+        if !pixel_format.is_supported() {
+            return Err(Error::UnsupportedPixelFormat(pixel_format));
         }
-        info!("\n{:#?}\n{:#?}", fixed_info, variable_info);
-        info!("mapping framebuffer");
-        let map = unsafe {
-            MmapOptions::new()
-                .len(fixed_info.smem_len as usize)
-                .map_mut(&file)?
+        let rotation = backend.rotation();
+        let mut canvas = Canvas::with_pixel_format(0, 0, pixel_format);
+        canvas.track_damage = true;
+        let mut frame_buffer = Self {
+            canvas,
+            backend,
+            logical_resolution: None,
+            converted: Vec::new(),
+            scaled: Vec::new(),
+            oriented: Vec::new(),
+            border: BLACK,
+            rotation,
+            flip: (false, false),
+            deferred: Vec::new(),
         };
-        Ok(Self {
-            file,
-            buffer: vec![0; fixed_info.smem_len as usize],
-            map,
-            fixed_info,
-            variable_info,
-        })
+        frame_buffer.resize();
+        Ok(frame_buffer)
     }
 
+    /// Size of the logical canvas drawing functions target.
     #[must_use]
     pub(crate) const fn screen_size(&self) -> (u32, u32) {
-        (self.variable_info.xres, self.variable_info.yres)
+        (self.canvas.width, self.canvas.height)
+    }
+
+    /// Size of the display as seen by the game, after rotation.
+    pub(crate) fn oriented_size(&self) -> (u32, u32) {
+        let (width, height) = self.backend.size();
+        if self.rotation.is_sideways() {
+            (height, width)
+        } else {
+            (width, height)
+        }
     }
 
-    pub(crate) fn wait_until_vsync(&self) -> Result<()> {
-        let mut dummy = 0;
-        if unsafe {
-            ioctl(
-                self.file.as_raw_fd(),
-                IoctlRequest::FbioWaitforvsync as _,
-                &mut dummy,
-            )
-        } == -1
-        {
-            return Err(Error::Ioctl(unsafe { *__errno_location() }));
+    /// Reallocate the back buffer to the logical resolution, clearing it.
+    pub(crate) fn resize(&mut self) {
+        let (width, height) = self
+            .logical_resolution
+            .unwrap_or_else(|| self.oriented_size());
+        let canvas = Canvas::with_pixel_format(width, height, self.canvas.pixel_format);
+        let old = std::mem::replace(&mut self.canvas, canvas);
+        self.canvas.track_damage = old.track_damage;
+        self.canvas.blend_mode = old.blend_mode;
+        self.canvas.camera = old.camera;
+        if let Some(palette) = &old.palette {
+            self.canvas.set_palette(palette);
+        }
+        self.canvas.mark_all_dirty();
+        self.scaled.clear();
+    }
+
+    /// Show the back buffer on the backend, scaling it up to the display resolution and rotating
+    /// it into the display orientation if needed. Only regions marked dirty are updated, unless
+    /// damage tracking is off.
+    pub(crate) fn present(&mut self) -> Result<()> {
+        let (width, height) = self.oriented_size();
+        let canvas = &mut self.canvas;
+        let scaling = (canvas.width, canvas.height) != (width, height);
+        let bytes_per_pixel = canvas.pixel_format.bytes_per_pixel();
+        let full = if scaling && self.scaled.is_empty() {
+            let border = canvas.pixel_format.pack(self.border);
+            self.scaled = border[..bytes_per_pixel].repeat(width as usize * height as usize);
+            // the border needs presenting too
+            true
+        } else {
+            !canvas.track_damage || canvas.fully_damaged
+        };
+        let mut regions = if full {
+            vec![Region::new(0, 0, canvas.width, canvas.height)]
+        } else {
+            std::mem::take(&mut canvas.damage)
+        };
+        canvas.damage.clear();
+        canvas.fully_damaged = false;
+        if regions.is_empty() {
+            return Ok(());
         }
-        Ok(())
+        let stride = width as usize * bytes_per_pixel;
+        let (buffer, buffer_stride) = if let Some(palette) = &canvas.palette {
+            let converted_stride = canvas.width as usize * bytes_per_pixel;
+            palette.convert_into(canvas, &regions, &mut self.converted);
+            (&self.converted, converted_stride)
+        } else {
+            (&canvas.buffer, canvas.stride)
+        };
+        let (frame, frame_stride) = if scaling {
+            for region in &mut regions {
+                *region =
+                    scale::scale_region(*region, (canvas.width, canvas.height), (width, height));
+                scale::scale_into(
+                    buffer,
+                    (canvas.width, canvas.height, buffer_stride),
+                    &mut self.scaled,
+                    (width, height, stride),
+                    bytes_per_pixel,
+                    region.y as usize..region.bottom() as usize,
+                );
+            }
+            if full {
+                regions = vec![Region::new(0, 0, width, height)];
+            }
+            (&self.scaled, stride)
+        } else {
+            (buffer, buffer_stride)
+        };
+        let flip = self.flip;
+        let (frame, frame_stride) = if self.rotation == Rotation::None && flip == (false, false) {
+            (frame, frame_stride)
+        } else {
+            let (physical_width, physical_height) = self.backend.size();
+            let physical_stride = physical_width as usize * bytes_per_pixel;
+            self.oriented
+                .resize(physical_stride * physical_height as usize, 0);
+            for region in &mut regions {
+                *region =
+                    transform::transform_region(*region, (width, height), self.rotation, flip);
+                transform::transform_into(
+                    frame,
+                    (width, height, frame_stride),
+                    &mut self.oriented,
+                    physical_stride,
+                    bytes_per_pixel,
+                    self.rotation,
+                    flip,
+                    *region,
+                );
+            }
+            (&self.oriented, physical_stride)
+        };
+        if full {
+            self.backend.present(frame, frame_stride)
+        } else {
+            self.backend.present_regions(frame, frame_stride, &regions)
+        }
+    }
+}
+
+/// Clockwise rotation applied to frames when they are shown, for displays mounted sideways or
+/// upside down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    /// Shown as drawn.
+    #[default]
+    None,
+    /// Rotated a quarter turn clockwise.
+    Clockwise90,
+    /// Rotated a half turn.
+    Rotate180,
+    /// Rotated three quarter turns clockwise, i.e. a quarter turn anticlockwise.
+    Clockwise270,
+}
+
+impl Rotation {
+    /// Whether the rotation swaps the width and height.
+    #[must_use]
+    pub const fn is_sideways(self) -> bool {
+        matches!(self, Self::Clockwise90 | Self::Clockwise270)
+    }
+}
+
+/// Use `backend` to show frames instead of the default framebuffer device.
+///
+/// Must be called before anything else in pigame touches the screen.
+///
+/// # Errors
+///
+/// If the display has already been initialised, an error is returned.
+pub fn set_display_backend<B: DisplayBackend + 'static>(backend: B) -> Result<()> {
+    set_backend(Box::new(backend))
+}
+
+/// Draw to a logical canvas of `width` by `height` pixels instead of the physical screen.
+///
+/// Each frame the canvas is scaled up by the largest whole factor that fits the display and
+/// centred, with the rest of the display filled with the border colour (see
+/// [`set_border_colour`]). [`screen_width`] and [`screen_height`] report the logical size.
+///
+/// The contents of the canvas are cleared.
+pub fn set_logical_resolution(width: u32, height: u32) {
+    let frame_buffer = &mut get().frame_buffer;
+    frame_buffer.logical_resolution = Some((width, height));
+    frame_buffer.resize();
+}
+
+/// Draw to the display directly again after [`set_logical_resolution`].
+///
+/// The contents of the canvas are cleared.
+pub fn reset_logical_resolution() {
+    let frame_buffer = &mut get().frame_buffer;
+    frame_buffer.logical_resolution = None;
+    frame_buffer.resize();
+}
+
+/// Set the colour around the logical canvas when it does not fill the display.
+pub fn set_border_colour(colour: Colour) {
+    let frame_buffer = &mut get().frame_buffer;
+    frame_buffer.border = colour;
+    frame_buffer.scaled.clear();
+}
+
+/// Turn tracking of which parts of the screen changed on or off; it is on by default.
+///
+/// With tracking on, [`next_frame`] only sends the parts of the screen touched by drawing
+/// functions since the last frame to the display. Games that redraw everything every frame can
+/// turn it off to skip the bookkeeping.
+pub fn set_dirty_tracking(enabled: bool) {
+    let frame_buffer = &mut get().frame_buffer;
+    frame_buffer.canvas.track_damage = enabled;
+    frame_buffer.canvas.mark_all_dirty();
+}
+
+/// Rotate frames when they are shown, for displays mounted sideways or upside down.
+///
+/// Drawing is unaffected; [`screen_width`] and [`screen_height`] report the size of the rotated
+/// display unless a logical resolution is set. Defaults to the rotation reported by the backend.
+///
+/// The contents of the screen are cleared if the width and height swap.
+pub fn set_screen_rotation(rotation: Rotation) {
+    let frame_buffer = &mut get().frame_buffer;
+    let resize = rotation.is_sideways() != frame_buffer.rotation.is_sideways();
+    frame_buffer.rotation = rotation;
+    if resize {
+        frame_buffer.resize();
     }
 }
 
+/// Mirror frames when they are shown, before they are rotated.
+pub fn set_screen_flip(horizontal: bool, vertical: bool) {
+    let frame_buffer = &mut get().frame_buffer;
+    frame_buffer.flip = (horizontal, vertical);
+    frame_buffer.canvas.mark_all_dirty();
+}
+
+/// Get the width and height of the display in pixels, regardless of the logical resolution and
+/// rotation.
+#[must_use]
+pub fn physical_screen_size() -> (u32, u32) {
+    get().frame_buffer.backend.size()
+}
+
 /// Get the width of the screen.
 #[must_use]
 pub fn screen_width() -> u32 {
@@ -178,25 +325,50 @@ pub fn screen_height() -> u32 {
     get().frame_buffer.screen_size().1
 }
 
-/// Draw a rectangle on the screen.
-pub fn draw_rectangle(x: u32, y: u32, w: u32, h: u32, colour: Colour) {
-    let frame_buffer = &mut get().frame_buffer;
-    for x in x..x + w {
-        for y in y..y + h {
-            let start = (y * (frame_buffer.variable_info.xres) + x) as usize * 4;
-            let Some(slice) = frame_buffer.buffer.get_mut(start..start + 4) else {
-                break;
-            };
-            slice.copy_from_slice(&colour.to_bgra_bytes());
-        }
-    }
+/// Draw a rectangle on the screen with its top left corner at `(x, y)`.
+///
+/// Parts falling outside the screen are cut off, and nothing is drawn unless `w` and `h` are
+/// positive.
+pub fn draw_rectangle(x: i32, y: i32, w: i32, h: i32, colour: Colour) {
+    get().frame_buffer.canvas.draw_rectangle(x, y, w, h, colour);
 }
 
-/// Clear the screen to a colour.
+/// Draw the outline of a rectangle on the screen with its top left corner at `(x, y)`,
+/// `thickness` pixels wide inside its edges.
+///
+/// Parts falling outside the screen are cut off, and nothing is drawn unless `w` and `h` are
+/// positive.
+pub fn draw_rectangle_lines(x: i32, y: i32, w: i32, h: i32, thickness: u32, colour: Colour) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_rectangle_lines(x, y, w, h, thickness, colour);
+}
+
+/// Read back the colour of a pixel in the frame being drawn.
+///
+/// Returns `None` if the position is outside the screen.
+#[must_use]
+pub fn get_pixel(x: u32, y: u32) -> Option<Colour> {
+    get().frame_buffer.canvas.get_pixel(x, y)
+}
+
+/// Clear the screen to a colour, replacing what was there whatever the blend mode.
 pub fn clear_background(colour: Colour) {
-    let frame_buffer = &mut get().frame_buffer;
-    let src = &vec![colour.to_bgra_bytes(); frame_buffer.buffer.len() / 4].into_flattened();
-    frame_buffer.buffer.copy_from_slice(src);
+    get().frame_buffer.canvas.clear(colour);
+}
+
+/// Set how drawing on the screen combines colours with what is already there; alpha blending by
+/// default.
+pub fn set_blend_mode(blend_mode: BlendMode) {
+    get().frame_buffer.canvas.set_blend_mode(blend_mode);
+}
+
+/// Draw all of `canvas` on the screen with its top left corner at `(x, y)`.
+///
+/// Parts falling outside the screen are cut off.
+pub fn draw_canvas(canvas: &Canvas, x: i32, y: i32) {
+    get().frame_buffer.canvas.draw_canvas(canvas, x, y);
 }
 
 /// Get the time since the program started.
@@ -205,17 +377,20 @@ pub fn get_time() -> f64 {
     get().start_time.elapsed().as_secs_f64()
 }
 
-/// Wait until the next frame and update the screen.
+/// Draw everything put off with [`layer::draw_on_layer`], then wait until the next frame and
+/// update the screen.
 ///
 /// # Errors
 ///
 /// If the `ioctl` call fails when waiting for the next frame, an error is returned.
 pub fn next_frame() -> Result<()> {
+    layer::flush_layers();
     let context = get();
     context.last_frame = Instant::now();
+    recording::update(context)?;
     let frame_buffer = &mut context.frame_buffer;
-    frame_buffer.wait_until_vsync()?;
-    frame_buffer.map.copy_from_slice(&frame_buffer.buffer);
+    frame_buffer.backend.wait_for_vsync()?;
+    frame_buffer.present()?;
     info!("fps: {}", 1. / get_frame_time().as_secs_f64());
     Ok(())
 }
@@ -225,3 +400,75 @@ pub fn next_frame() -> Result<()> {
 pub fn get_frame_time() -> Duration {
     get().last_frame.elapsed()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::colour::{BLUE, RED};
+    use super::screenshot::screenshot_rgba;
+    use super::testing::{screen, SIZE};
+    use super::*;
+
+    #[test]
+    fn draw_rectangle_fills_only_its_pixels() {
+        let _screen = screen();
+        draw_rectangle(2, 3, 4, 5, RED);
+        assert_eq!(get_pixel(2, 3), Some(RED));
+        assert_eq!(get_pixel(5, 7), Some(RED));
+        assert_eq!(get_pixel(1, 3), Some(BLACK));
+        assert_eq!(get_pixel(6, 3), Some(BLACK));
+        assert_eq!(get_pixel(2, 8), Some(BLACK));
+    }
+
+    #[test]
+    fn draw_rectangle_cuts_off_outside_the_screen() {
+        let _screen = screen();
+        draw_rectangle(-5, -5, i32::MAX, 7, BLUE);
+        assert_eq!(get_pixel(0, 0), Some(BLUE));
+        assert_eq!(get_pixel(SIZE.0 - 1, 1), Some(BLUE));
+        assert_eq!(get_pixel(0, 2), Some(BLACK));
+        assert_eq!(get_pixel(SIZE.0, 0), None);
+    }
+
+    #[test]
+    fn clear_background_replaces_every_pixel() {
+        let _screen = screen();
+        set_blend_mode(BlendMode::Additive);
+        draw_rectangle(0, 0, 4, 4, RED);
+        clear_background(BLUE.with_alpha(0));
+        let pixels = screenshot_rgba();
+        assert_eq!(pixels.len(), SIZE.0 as usize * SIZE.1 as usize * 4);
+        assert!(pixels
+            .chunks_exact(4)
+            .all(|pixel| pixel == [0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn next_frame_shows_the_screen_on_the_display() {
+        let (_guard, display) = screen();
+        draw_rectangle(1, 0, 1, 1, RED);
+        next_frame().expect("the headless display cannot fail");
+        let frame = display.frame();
+        assert_eq!(frame[..8], [0, 0, 0, 0, 0, 0, 255, 0]);
+    }
+
+    #[test]
+    fn next_frame_only_shows_what_changed() {
+        let (_guard, display) = screen();
+        next_frame().expect("the headless display cannot fail");
+        assert_eq!(display.last_regions(), [Region::new(0, 0, SIZE.0, SIZE.1)]);
+        draw_rectangle(2, 3, 4, 5, RED);
+        next_frame().expect("the headless display cannot fail");
+        assert_eq!(display.last_regions(), [Region::new(2, 3, 4, 5)]);
+    }
+
+    #[test]
+    fn next_frame_shows_the_border_once_when_scaling() {
+        let (_guard, display) = screen();
+        set_logical_resolution(SIZE.0 / 2, SIZE.1 / 2);
+        next_frame().expect("the headless display cannot fail");
+        assert_eq!(display.last_regions(), [Region::new(0, 0, SIZE.0, SIZE.1)]);
+        draw_rectangle(1, 2, 1, 1, RED);
+        next_frame().expect("the headless display cannot fail");
+        assert_eq!(display.last_regions(), [Region::new(2, 4, 2, 2)]);
+    }
+}
@@ -0,0 +1,348 @@
+/// Linux framebuffer (`/dev/fbN`) backend.
+pub mod fbdev;
+/// In-memory backend for tests and CI.
+pub mod headless;
+/// Backend drawing into a terminal.
+pub mod terminal;
+/// Backend serving frames to VNC clients.
+pub mod vnc;
+
+use crate::error::Result;
+use crate::input::Input;
+
+use super::colour::Colour;
+use super::Rotation;
+
+pub use self::fbdev::{DisplayMode, FbdevBackend};
+pub use self::headless::HeadlessBackend;
+pub use self::terminal::TerminalBackend;
+pub use self::vnc::VncBackend;
+
+/// Position and width of one colour channel inside a packed pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channel {
+    /// Bit offset of the channel, counted from the least significant bit.
+    pub offset: u32,
+    /// Number of bits in the channel; 0 if the channel is absent.
+    pub length: u32,
+}
+
+impl Channel {
+    /// Create a new channel description.
+    #[must_use]
+    pub const fn new(offset: u32, length: u32) -> Self {
+        Self { offset, length }
+    }
+
+    const fn mask(self) -> u32 {
+        if self.length == 0 {
+            0
+        } else {
+            u32::MAX >> (32 - self.length)
+        }
+    }
+
+    fn pack(self, value: u8) -> u32 {
+        if self.length == 0 {
+            return 0;
+        }
+        let value = if self.length >= 8 {
+            u32::from(value) << (self.length - 8)
+        } else {
+            u32::from(value) >> (8 - self.length)
+        };
+        value << self.offset
+    }
+
+    fn unpack(self, pixel: u32) -> u8 {
+        let mask = self.mask();
+        if mask == 0 {
+            return 0;
+        }
+        let value = (pixel >> self.offset) & mask;
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            (u64::from(value) * 255 / u64::from(mask)) as u8
+        }
+    }
+}
+
+/// Layout of a single pixel in the buffers handed to a [`DisplayBackend`].
+///
+/// Pixels are stored little-endian in `bits_per_pixel / 8` bytes, with each colour channel at the
+/// bit position given by its [`Channel`], like the bitfields reported by the Linux framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelFormat {
+    /// Bits used to store a single pixel; 16, 24 or 32.
+    pub bits_per_pixel: u32,
+    /// Red channel.
+    pub red: Channel,
+    /// Green channel.
+    pub green: Channel,
+    /// Blue channel.
+    pub blue: Channel,
+    /// Alpha (transparency) channel; usually absent.
+    pub alpha: Channel,
+}
+
+impl PixelFormat {
+    /// 32 bits per pixel; blue, green, red, then an unused byte in memory.
+    pub const BGRX8888: Self = Self::new(
+        32,
+        Channel::new(16, 8),
+        Channel::new(8, 8),
+        Channel::new(0, 8),
+    );
+    /// 32 bits per pixel; red, green, blue, then an unused byte in memory.
+    pub const RGBX8888: Self = Self::new(
+        32,
+        Channel::new(0, 8),
+        Channel::new(8, 8),
+        Channel::new(16, 8),
+    );
+    /// 24 bits per pixel; blue, green, then red in memory.
+    pub const BGR888: Self = Self::new(
+        24,
+        Channel::new(16, 8),
+        Channel::new(8, 8),
+        Channel::new(0, 8),
+    );
+    /// 24 bits per pixel; red, green, then blue in memory.
+    pub const RGB888: Self = Self::new(
+        24,
+        Channel::new(0, 8),
+        Channel::new(8, 8),
+        Channel::new(16, 8),
+    );
+    /// 32 bits per pixel; blue, green, red, then alpha in memory.
+    pub const BGRA8888: Self = Self::BGRX8888.with_alpha(Channel::new(24, 8));
+    /// 32 bits per pixel; red, green, blue, then alpha in memory.
+    pub const RGBA8888: Self = Self::RGBX8888.with_alpha(Channel::new(24, 8));
+    /// 16 bits per pixel; 5 bits of red in the high bits, 6 of green, 5 of blue in the low bits.
+    pub const RGB565: Self = Self::new(
+        16,
+        Channel::new(11, 5),
+        Channel::new(5, 6),
+        Channel::new(0, 5),
+    );
+    /// 16 bits per pixel; 5 bits of blue in the high bits, 6 of green, 5 of red in the low bits.
+    pub const BGR565: Self = Self::new(
+        16,
+        Channel::new(0, 5),
+        Channel::new(5, 6),
+        Channel::new(11, 5),
+    );
+
+    /// Create a pixel format without an alpha channel.
+    #[must_use]
+    pub const fn new(bits_per_pixel: u32, red: Channel, green: Channel, blue: Channel) -> Self {
+        Self {
+            bits_per_pixel,
+            red,
+            green,
+            blue,
+            alpha: Channel::new(0, 0),
+        }
+    }
+
+    /// Return the same pixel format with an alpha channel.
+    #[must_use]
+    pub const fn with_alpha(self, alpha: Channel) -> Self {
+        Self { alpha, ..self }
+    }
+
+    /// Whether pixels store an alpha component.
+    #[must_use]
+    pub const fn has_alpha(self) -> bool {
+        self.alpha.length != 0
+    }
+
+    /// Number of bytes used to store a single pixel.
+    #[must_use]
+    pub const fn bytes_per_pixel(self) -> usize {
+        self.bits_per_pixel.div_ceil(8) as usize
+    }
+
+    /// Whether pigame can draw into buffers of this format.
+    #[must_use]
+    pub const fn is_supported(self) -> bool {
+        matches!(self.bits_per_pixel, 16 | 24 | 32)
+            && self.red.length <= 8
+            && self.green.length <= 8
+            && self.blue.length <= 8
+            && self.alpha.length <= 8
+    }
+
+    /// Pack a colour into the bytes of a single pixel. Only the first
+    /// [`bytes_per_pixel`](Self::bytes_per_pixel) bytes are meaningful.
+    #[must_use]
+    pub fn pack(self, colour: Colour) -> [u8; 4] {
+        (self.red.pack(colour.red)
+            | self.green.pack(colour.green)
+            | self.blue.pack(colour.blue)
+            | self.alpha.pack(colour.alpha))
+        .to_le_bytes()
+    }
+
+    /// Unpack the colour of a single pixel from its bytes. Pixels are opaque unless the format
+    /// has an alpha channel.
+    #[must_use]
+    pub fn unpack(self, bytes: &[u8]) -> Colour {
+        let mut pixel = [0; 4];
+        let len = self.bytes_per_pixel().min(bytes.len());
+        pixel[..len].copy_from_slice(&bytes[..len]);
+        let pixel = u32::from_le_bytes(pixel);
+        Colour::rgba(
+            self.red.unpack(pixel),
+            self.green.unpack(pixel),
+            self.blue.unpack(pixel),
+            if self.has_alpha() {
+                self.alpha.unpack(pixel)
+            } else {
+                u8::MAX
+            },
+        )
+    }
+}
+
+/// A rectangular region of a frame, in whole pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    /// X coordinate of the left edge
+    pub x: u32,
+    /// Y coordinate of the top edge
+    pub y: u32,
+    /// Width
+    pub width: u32,
+    /// Height
+    pub height: u32,
+}
+
+impl Region {
+    /// Create a new region.
+    #[must_use]
+    pub const fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Whether the region covers no pixels.
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// X coordinate just past the right edge.
+    #[must_use]
+    pub const fn right(self) -> u32 {
+        self.x + self.width
+    }
+
+    /// Y coordinate just past the bottom edge.
+    #[must_use]
+    pub const fn bottom(self) -> u32 {
+        self.y + self.height
+    }
+
+    /// The smallest region containing both regions.
+    #[must_use]
+    pub fn union(self, other: Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Self::new(
+            x,
+            y,
+            self.right().max(other.right()) - x,
+            self.bottom().max(other.bottom()) - y,
+        )
+    }
+
+    /// Whether the regions overlap or share an edge.
+    #[must_use]
+    pub const fn touches(self, other: Self) -> bool {
+        self.x <= other.right()
+            && other.x <= self.right()
+            && self.y <= other.bottom()
+            && other.y <= self.bottom()
+    }
+
+    /// The part of the region inside a frame of `width` by `height` pixels.
+    #[must_use]
+    pub fn clip(self, width: u32, height: u32) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        Self::new(
+            x,
+            y,
+            self.right().min(width) - x,
+            self.bottom().min(height) - y,
+        )
+    }
+}
+
+/// Something that can show frames drawn by pigame.
+///
+/// The drawing functions in [`graphics`](crate::graphics) render into a back buffer laid out
+/// according to [`pixel_format`](Self::pixel_format), which is handed to
+/// [`present`](Self::present) once per [`next_frame`](crate::graphics::next_frame).
+///
+/// Use [`set_display_backend`](crate::graphics::set_display_backend) to pick a backend at
+/// startup; if none is set, [`FbdevBackend`] is opened on `/dev/fb0`.
+pub trait DisplayBackend {
+    /// Width and height of the display in pixels.
+    fn size(&self) -> (u32, u32);
+
+    /// Pixel layout the back buffer must use.
+    fn pixel_format(&self) -> PixelFormat;
+
+    /// Show a frame. `buffer` holds `height` rows of `width` pixels in
+    /// [`pixel_format`](Self::pixel_format), each row starting `stride` bytes after the previous
+    /// one.
+    ///
+    /// # Errors
+    ///
+    /// If the frame cannot be shown, an error is returned.
+    fn present(&mut self, buffer: &[u8], stride: usize) -> Result<()>;
+
+    /// Show a frame that only differs from the last one inside `regions`. Arguments are as for
+    /// [`present`](Self::present), which is called by default.
+    ///
+    /// # Errors
+    ///
+    /// If the frame cannot be shown, an error is returned.
+    fn present_regions(&mut self, buffer: &[u8], stride: usize, regions: &[Region]) -> Result<()> {
+        let _ = regions;
+        self.present(buffer, stride)
+    }
+
+    /// Rotation to apply to frames by default, for displays known to be mounted sideways.
+    fn rotation(&self) -> Rotation {
+        Rotation::None
+    }
+
+    /// Whether `input` is held, for backends that read input themselves, such as from a
+    /// keyboard. `None` defers to the GPIO pins.
+    fn is_input_active(&mut self, input: Input) -> Option<bool> {
+        let _ = input;
+        None
+    }
+
+    /// Whether the player asked the game to quit through the backend, such as by pressing Ctrl-C
+    /// in a terminal it has taken over. Backends without a way to ask return `false`.
+    fn is_quit_requested(&mut self) -> bool {
+        false
+    }
+
+    /// Block until the next vertical blank. Backends without vsync return immediately.
+    ///
+    /// # Errors
+    ///
+    /// If waiting for vsync fails, an error is returned.
+    fn wait_for_vsync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,519 @@
+use std::fs::{File, OpenOptions};
+use std::mem::zeroed;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::Path;
+use std::sync::Once;
+
+use libc::{__errno_location, atexit, ioctl};
+use log::{info, warn};
+use memmap::{MmapMut, MmapOptions};
+
+use crate::error::{Error, Result};
+
+use super::{Channel, DisplayBackend, PixelFormat, Region};
+use crate::graphics::Rotation;
+
+/// Display backend drawing to a Linux framebuffer device through a memory map.
+///
+/// If the virtual resolution is at least twice the visible height and the driver supports
+/// panning, frames are written to the off-screen page and shown with `FBIOPAN_DISPLAY`, so a slow
+/// copy never tears. Otherwise frames are copied straight into the visible area.
+#[derive(Debug)]
+pub struct FbdevBackend {
+    /// Before `device`, so it is unmapped before the mode is put back.
+    map: MmapMut,
+    device: Device,
+    variable_info: VarScreeninfo,
+    pixel_format: PixelFormat,
+    line_length: usize,
+    page_flipping: bool,
+    front_page: u32,
+    /// Regions changed by the last frame, which the back page is missing when page flipping.
+    previous_regions: Vec<Region>,
+}
+
+/// Open framebuffer device, which puts back the mode it was in before it was opened, if that was
+/// changed, when dropped.
+#[derive(Debug)]
+struct Device {
+    file: File,
+    original_mode: Option<VarScreeninfo>,
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        if let Some(mut mode) = self.original_mode.take() {
+            info!("restoring display mode");
+            let fd = self.file.as_raw_fd();
+            unsafe {
+                ioctl(fd, IoctlRequest::FbioputVscreeninfo as _, &mut mode);
+                #[allow(static_mut_refs)]
+                if matches!(&RESTORE_ON_EXIT, Some((restore_fd, _)) if *restore_fd == fd) {
+                    RESTORE_ON_EXIT = None;
+                }
+            }
+        }
+    }
+}
+
+/// A display mode to request from the framebuffer driver with [`FbdevBackend::with_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayMode {
+    /// Visible width in pixels.
+    pub width: u32,
+    /// Visible height in pixels.
+    pub height: u32,
+    /// Bits per pixel, or `None` to keep the current depth.
+    pub bits_per_pixel: Option<u32>,
+    /// Height of the virtual screen, or `None` to match the visible height. At least twice the
+    /// visible height enables page flipping.
+    pub virtual_height: Option<u32>,
+}
+
+impl DisplayMode {
+    /// Create a mode with the given resolution, keeping the current depth.
+    #[must_use]
+    pub const fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            bits_per_pixel: None,
+            virtual_height: None,
+        }
+    }
+
+    /// Request a depth of `bits_per_pixel`.
+    #[must_use]
+    pub const fn with_bits_per_pixel(self, bits_per_pixel: u32) -> Self {
+        Self {
+            bits_per_pixel: Some(bits_per_pixel),
+            ..self
+        }
+    }
+
+    /// Request a virtual screen two pages tall, for page flipping.
+    #[must_use]
+    pub const fn double_buffered(self) -> Self {
+        Self {
+            virtual_height: Some(self.height * 2),
+            ..self
+        }
+    }
+}
+
+/// Mode to put back if the process exits while a backend that changed it is still alive, which
+/// is the usual case since the global context is never dropped.
+static mut RESTORE_ON_EXIT: Option<(RawFd, VarScreeninfo)> = None;
+static REGISTER_RESTORE_ON_EXIT: Once = Once::new();
+
+extern "C" fn restore_on_exit() {
+    #[allow(static_mut_refs)]
+    if let Some((fd, mut mode)) = unsafe { RESTORE_ON_EXIT.take() } {
+        unsafe { ioctl(fd, IoctlRequest::FbioputVscreeninfo as _, &mut mode) };
+    }
+}
+
+#[repr(u64)]
+pub(crate) enum IoctlRequest {
+    FbiogetVscreeninfo = 0x4600,
+    FbioputVscreeninfo = 0x4601,
+    FbiogetFscreeninfo = 0x4602,
+    FbioPanDisplay = 0x4606,
+    FbioWaitforvsync = 0x4004_4620,
+}
+
+const FB_ACTIVATE_NOW: u32 = 0;
+const FB_ROTATE_CW: u32 = 1;
+const FB_ROTATE_UD: u32 = 2;
+const FB_ROTATE_CCW: u32 = 3;
+
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub(crate) struct Bitfield {
+    pub(crate) offset: u32,
+    pub(crate) length: u32,
+    pub(crate) msb_right: u32,
+}
+
+impl From<&Bitfield> for Channel {
+    fn from(bitfield: &Bitfield) -> Self {
+        Self::new(bitfield.offset, bitfield.length)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub(crate) struct VarScreeninfo {
+    pub(crate) xres: u32,
+    pub(crate) yres: u32,
+    pub(crate) xres_virtual: u32,
+    pub(crate) yres_virtual: u32,
+    pub(crate) xoffset: u32,
+    pub(crate) yoffset: u32,
+    pub(crate) bits_per_pixel: u32,
+    pub(crate) grayscale: u32,
+    pub(crate) red: Bitfield,
+    pub(crate) green: Bitfield,
+    pub(crate) blue: Bitfield,
+    pub(crate) transp: Bitfield,
+    pub(crate) nonstd: u32,
+    pub(crate) activate: u32,
+    pub(crate) height: u32,
+    pub(crate) width: u32,
+    pub(crate) accel_flags: u32,
+    pub(crate) pixclock: u32,
+    pub(crate) left_margin: u32,
+    pub(crate) right_margin: u32,
+    pub(crate) upper_margin: u32,
+    pub(crate) lower_margin: u32,
+    pub(crate) hsync_len: u32,
+    pub(crate) vsync_len: u32,
+    pub(crate) sync: u32,
+    pub(crate) vmode: u32,
+    pub(crate) rotate: u32,
+    pub(crate) colorspace: u32,
+    pub(crate) reserved: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct FixScreeninfo {
+    pub(crate) id: [u8; 16],
+    pub(crate) smem_start: usize,
+    pub(crate) smem_len: u32,
+    pub(crate) r#type: u32,
+    pub(crate) type_aux: u32,
+    pub(crate) visual: u32,
+    pub(crate) xpanstep: u16,
+    pub(crate) ypanstep: u16,
+    pub(crate) ywrapstep: u16,
+    pub(crate) line_length: u32,
+    pub(crate) mmio_start: usize,
+    pub(crate) mmio_len: u32,
+    pub(crate) accel: u32,
+    pub(crate) capabilities: u16,
+    pub(crate) reserved: [u16; 2],
+}
+
+impl FbdevBackend {
+    /// Open the default framebuffer device, `/dev/fb0`.
+    ///
+    /// # Errors
+    ///
+    /// If the device cannot be opened, queried or mapped, an error is returned.
+    pub fn new() -> Result<Self> {
+        Self::open("/dev/fb0")
+    }
+
+    /// Open the framebuffer device at `path`.
+    ///
+    /// # Errors
+    ///
+    /// If the device cannot be opened, queried or mapped, an error is returned.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_impl(path.as_ref(), None)
+    }
+
+    /// Open `/dev/fb0` and switch it to `mode`. The original mode is restored when the backend is
+    /// dropped or the program exits.
+    ///
+    /// # Errors
+    ///
+    /// If the device cannot be opened, queried or mapped, or the driver rejects the mode, an error
+    /// is returned.
+    pub fn with_mode(mode: DisplayMode) -> Result<Self> {
+        Self::open_with_mode("/dev/fb0", mode)
+    }
+
+    /// Open the framebuffer device at `path` and switch it to `mode`. The original mode is
+    /// restored when the backend is dropped or the program exits.
+    ///
+    /// # Errors
+    ///
+    /// If the device cannot be opened, queried or mapped, or the driver rejects the mode, an error
+    /// is returned.
+    pub fn open_with_mode<P: AsRef<Path>>(path: P, mode: DisplayMode) -> Result<Self> {
+        Self::open_impl(path.as_ref(), Some(mode))
+    }
+
+    fn open_impl(path: &Path, mode: Option<DisplayMode>) -> Result<Self> {
+        info!("opening framebuffer device");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let mut variable_info = get_variable_info(&file)?;
+        let device = match mode {
+            Some(mode) => {
+                info!("setting display mode {mode:?}");
+                let original_mode = variable_info.clone();
+                variable_info.xres = mode.width;
+                variable_info.yres = mode.height;
+                variable_info.xres_virtual = mode.width;
+                variable_info.yres_virtual = mode.virtual_height.unwrap_or(mode.height);
+                variable_info.xoffset = 0;
+                variable_info.yoffset = 0;
+                if let Some(bits_per_pixel) = mode.bits_per_pixel {
+                    variable_info.bits_per_pixel = bits_per_pixel;
+                }
+                variable_info.activate = FB_ACTIVATE_NOW;
+                if unsafe {
+                    ioctl(
+                        file.as_raw_fd(),
+                        IoctlRequest::FbioputVscreeninfo as _,
+                        &mut variable_info,
+                    )
+                } == -1
+                {
+                    return Err(Error::Ioctl(unsafe { *__errno_location() }));
+                }
+                // from here on, failing to open drops the device, putting the mode back
+                let device = Device {
+                    file,
+                    original_mode: Some(original_mode),
+                };
+                // the driver may have adjusted the mode, so read back what it actually set
+                variable_info = get_variable_info(&device.file)?;
+                device
+            }
+            None => Device {
+                file,
+                original_mode: None,
+            },
+        };
+        let fixed_info = get_fixed_info(&device.file)?;
+        info!("\n{:#?}\n{:#?}", fixed_info, variable_info);
+        let pixel_format = PixelFormat {
+            bits_per_pixel: variable_info.bits_per_pixel,
+            red: (&variable_info.red).into(),
+            green: (&variable_info.green).into(),
+            blue: (&variable_info.blue).into(),
+            alpha: (&variable_info.transp).into(),
+        };
+        if !pixel_format.is_supported() {
+            return Err(Error::UnsupportedPixelFormat(pixel_format));
+        }
+        // some drivers leave `line_length` unset, in which case rows are packed across the
+        // virtual width
+        let line_length = if fixed_info.line_length == 0 {
+            variable_info.xres_virtual as usize * pixel_format.bytes_per_pixel()
+        } else {
+            fixed_info.line_length as usize
+        };
+        info!("mapping framebuffer");
+        let map = unsafe {
+            MmapOptions::new()
+                .len(fixed_info.smem_len as usize)
+                .map_mut(&device.file)?
+        };
+        // only once nothing else can fail, as the backend is usually never dropped
+        if let Some(original_mode) = &device.original_mode {
+            unsafe { RESTORE_ON_EXIT = Some((device.file.as_raw_fd(), original_mode.clone())) };
+            REGISTER_RESTORE_ON_EXIT.call_once(|| unsafe {
+                atexit(restore_on_exit);
+            });
+        }
+        let mut backend = Self {
+            map,
+            device,
+            variable_info,
+            pixel_format,
+            line_length,
+            page_flipping: false,
+            front_page: 0,
+            previous_regions: Vec::new(),
+        };
+        if backend.variable_info.yres_virtual >= 2 * backend.variable_info.yres
+            && fixed_info.ypanstep != 0
+        {
+            info!("enabling page flipping");
+            match backend.pan(0) {
+                Ok(()) => backend.page_flipping = true,
+                Err(error) => warn!("cannot pan display, copying frames instead: {error}"),
+            }
+        }
+        Ok(backend)
+    }
+
+    /// Whether frames are shown by flipping between two pages rather than copying.
+    #[must_use]
+    pub const fn is_page_flipping(&self) -> bool {
+        self.page_flipping
+    }
+
+    fn pan(&mut self, yoffset: u32) -> Result<()> {
+        let previous = self.variable_info.yoffset;
+        self.variable_info.yoffset = yoffset;
+        if unsafe {
+            ioctl(
+                self.device.file.as_raw_fd(),
+                IoctlRequest::FbioPanDisplay as _,
+                &mut self.variable_info,
+            )
+        } == -1
+        {
+            self.variable_info.yoffset = previous;
+            return Err(Error::Ioctl(unsafe { *__errno_location() }));
+        }
+        Ok(())
+    }
+
+    /// Copy a frame into the mapped memory, with its top row at `yoffset`.
+    fn copy_frame(&mut self, buffer: &[u8], stride: usize, yoffset: u32) -> Result<()> {
+        let origin = yoffset as usize * self.line_length
+            + self.variable_info.xoffset as usize * self.pixel_format.bytes_per_pixel();
+        if stride == self.line_length {
+            let Some(map) = self.map.get_mut(origin..origin + buffer.len()) else {
+                return Err(Error::OutOfBounds);
+            };
+            map.copy_from_slice(buffer);
+            return Ok(());
+        }
+        let (width, height) = self.size();
+        self.copy_region(buffer, stride, yoffset, Region::new(0, 0, width, height))
+    }
+
+    /// Copy one region of a frame into the mapped memory, with the top row of the frame at
+    /// `yoffset`.
+    fn copy_region(
+        &mut self,
+        buffer: &[u8],
+        stride: usize,
+        yoffset: u32,
+        region: Region,
+    ) -> Result<()> {
+        let (width, height) = self.size();
+        let region = region.clip(width, height);
+        let bytes_per_pixel = self.pixel_format.bytes_per_pixel();
+        let origin = yoffset as usize * self.line_length
+            + self.variable_info.xoffset as usize * bytes_per_pixel;
+        let left = region.x as usize * bytes_per_pixel;
+        let right = region.right() as usize * bytes_per_pixel;
+        for y in region.y as usize..region.bottom() as usize {
+            let source = buffer.get(y * stride + left..y * stride + right);
+            let start = origin + y * self.line_length;
+            let destination = self.map.get_mut(start + left..start + right);
+            let (Some(source), Some(destination)) = (source, destination) else {
+                return Err(Error::OutOfBounds);
+            };
+            destination.copy_from_slice(source);
+        }
+        Ok(())
+    }
+
+    /// Pan to the page at `yoffset`, which has just been drawn, giving up on page flipping if
+    /// that fails.
+    fn flip(&mut self, yoffset: u32) -> bool {
+        match self.pan(yoffset) {
+            Ok(()) => {
+                self.front_page = 1 - self.front_page;
+                true
+            }
+            Err(error) => {
+                warn!("cannot pan display, copying frames instead: {error}");
+                self.page_flipping = false;
+                false
+            }
+        }
+    }
+}
+
+fn get_fixed_info(file: &File) -> Result<FixScreeninfo> {
+    info!("getting framebuffer information (fixed)");
+    let mut fixed_info: FixScreeninfo = unsafe { zeroed() };
+    if unsafe {
+        ioctl(
+            file.as_raw_fd(),
+            IoctlRequest::FbiogetFscreeninfo as _,
+            &mut fixed_info,
+        )
+    } == -1
+    {
+        return Err(Error::Ioctl(unsafe { *__errno_location() }));
+    }
+    Ok(fixed_info)
+}
+
+fn get_variable_info(file: &File) -> Result<VarScreeninfo> {
+    info!("getting framebuffer information (variable)");
+    let mut variable_info: VarScreeninfo = unsafe { zeroed() };
+    if unsafe {
+        ioctl(
+            file.as_raw_fd(),
+            IoctlRequest::FbiogetVscreeninfo as _,
+            &mut variable_info,
+        )
+    } == -1
+    {
+        return Err(Error::Ioctl(unsafe { *__errno_location() }));
+    }
+    Ok(variable_info)
+}
+
+impl DisplayBackend for FbdevBackend {
+    fn size(&self) -> (u32, u32) {
+        (self.variable_info.xres, self.variable_info.yres)
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    fn present(&mut self, buffer: &[u8], stride: usize) -> Result<()> {
+        let (width, height) = self.size();
+        self.previous_regions = vec![Region::new(0, 0, width, height)];
+        if self.page_flipping {
+            let yoffset = (1 - self.front_page) * self.variable_info.yres;
+            self.copy_frame(buffer, stride, yoffset)?;
+            if self.flip(yoffset) {
+                return Ok(());
+            }
+        }
+        self.copy_frame(buffer, stride, self.variable_info.yoffset)
+    }
+
+    fn present_regions(&mut self, buffer: &[u8], stride: usize, regions: &[Region]) -> Result<()> {
+        if self.page_flipping {
+            // the back page still holds the frame before last, so it also needs whatever changed
+            // in the last frame
+            let yoffset = (1 - self.front_page) * self.variable_info.yres;
+            let previous_regions = std::mem::replace(&mut self.previous_regions, regions.to_vec());
+            for region in previous_regions.iter().chain(regions) {
+                self.copy_region(buffer, stride, yoffset, *region)?;
+            }
+            if self.flip(yoffset) {
+                return Ok(());
+            }
+        }
+        for region in regions {
+            self.copy_region(buffer, stride, self.variable_info.yoffset, *region)?;
+        }
+        Ok(())
+    }
+
+    fn rotation(&self) -> Rotation {
+        match self.variable_info.rotate {
+            FB_ROTATE_CW => Rotation::Clockwise90,
+            FB_ROTATE_UD => Rotation::Rotate180,
+            FB_ROTATE_CCW => Rotation::Clockwise270,
+            _ => Rotation::None,
+        }
+    }
+
+    fn wait_for_vsync(&mut self) -> Result<()> {
+        let mut dummy = 0;
+        if unsafe {
+            ioctl(
+                self.device.file.as_raw_fd(),
+                IoctlRequest::FbioWaitforvsync as _,
+                &mut dummy,
+            )
+        } == -1
+        {
+            return Err(Error::Ioctl(unsafe { *__errno_location() }));
+        }
+        Ok(())
+    }
+}
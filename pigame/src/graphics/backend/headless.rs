@@ -0,0 +1,108 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::error::{Error, Result};
+
+use super::{DisplayBackend, PixelFormat, Region};
+
+/// Display backend that keeps frames in memory and never touches any hardware.
+///
+/// Useful for tests and CI, where there is no framebuffer device to open. Read pixels back with
+/// [`get_pixel`](crate::graphics::get_pixel), or keep a clone of the backend before handing it to
+/// [`set_display_backend`](crate::graphics::set_display_backend) and read the frames it was
+/// shown with [`frame`](Self::frame); clones share the same frame.
+#[derive(Debug, Clone)]
+pub struct HeadlessBackend {
+    width: u32,
+    height: u32,
+    pixel_format: PixelFormat,
+    shown: Arc<Mutex<Shown>>,
+}
+
+/// What was last shown on a headless display.
+#[derive(Debug)]
+struct Shown {
+    frame: Vec<u8>,
+    regions: Vec<Region>,
+}
+
+impl HeadlessBackend {
+    /// Create a headless display with the given resolution, using
+    /// [`PixelFormat::BGRX8888`].
+    #[must_use]
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::with_pixel_format(width, height, PixelFormat::BGRX8888)
+    }
+
+    /// Create a headless display with the given resolution and pixel format.
+    #[must_use]
+    pub fn with_pixel_format(width: u32, height: u32, pixel_format: PixelFormat) -> Self {
+        let frame = vec![0; width as usize * height as usize * pixel_format.bytes_per_pixel()];
+        Self {
+            width,
+            height,
+            pixel_format,
+            shown: Arc::new(Mutex::new(Shown {
+                frame,
+                regions: Vec::new(),
+            })),
+        }
+    }
+
+    /// Get a copy of the frame as last shown, with rows of pixels packed in the pixel format of
+    /// the display and no padding between them.
+    #[must_use]
+    pub fn frame(&self) -> Vec<u8> {
+        self.shown().frame.clone()
+    }
+
+    /// Get the regions of the frame updated when it was last shown, or the whole frame if it was
+    /// shown in full.
+    #[must_use]
+    pub fn last_regions(&self) -> Vec<Region> {
+        self.shown().regions.clone()
+    }
+
+    fn shown(&self) -> MutexGuard<'_, Shown> {
+        self.shown.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl DisplayBackend for HeadlessBackend {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    fn present(&mut self, buffer: &[u8], stride: usize) -> Result<()> {
+        self.present_regions(
+            buffer,
+            stride,
+            &[Region::new(0, 0, self.width, self.height)],
+        )
+    }
+
+    fn present_regions(&mut self, buffer: &[u8], stride: usize, regions: &[Region]) -> Result<()> {
+        let bytes_per_pixel = self.pixel_format.bytes_per_pixel();
+        let frame_stride = self.width as usize * bytes_per_pixel;
+        let mut shown = self.shown();
+        shown.regions = regions.to_vec();
+        for region in regions {
+            let region = region.clip(self.width, self.height);
+            let left = region.x as usize * bytes_per_pixel;
+            let right = region.right() as usize * bytes_per_pixel;
+            for y in region.y as usize..region.bottom() as usize {
+                let source = buffer.get(y * stride + left..y * stride + right);
+                let Some(source) = source else {
+                    return Err(Error::OutOfBounds);
+                };
+                shown.frame[y * frame_stride + left..y * frame_stride + right]
+                    .copy_from_slice(source);
+            }
+        }
+        drop(shown);
+        Ok(())
+    }
+}
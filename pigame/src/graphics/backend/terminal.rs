@@ -0,0 +1,269 @@
+use std::fmt::Write as _;
+use std::io::{stdin, stdout, Read, Write};
+use std::mem::zeroed;
+use std::os::fd::AsRawFd;
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+use libc::{
+    atexit, ioctl, tcgetattr, tcsetattr, termios, winsize, ECHO, ICANON, ISIG, STDIN_FILENO,
+    TCSANOW, TIOCGWINSZ, VMIN, VTIME,
+};
+use strum::EnumCount;
+
+use crate::error::{Error, Result};
+use crate::input::Input;
+
+use super::{DisplayBackend, PixelFormat};
+
+/// Display backend drawing frames into the terminal with half-block characters and 24-bit ANSI
+/// colours.
+///
+/// Each character cell shows two pixels, one above the other. Frames are shrunk to fit the
+/// terminal, keeping their aspect ratio, and redrawn on every
+/// [`next_frame`](crate::graphics::next_frame). Keys pressed in the terminal drive
+/// [`is_active`](crate::input::is_active): arrow keys or WASD for the directions, Z and X for A
+/// and B, Enter for Start and Tab for Hotkey. Ctrl-C asks the game to quit, which it sees through
+/// [`is_quit_requested`](crate::input::is_quit_requested).
+#[derive(Debug)]
+pub struct TerminalBackend {
+    width: u32,
+    height: u32,
+    terminal_size: (u32, u32),
+    output: String,
+    keys: [KeyState; Input::COUNT],
+    quit_requested: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct KeyState {
+    last_press: Option<Instant>,
+    repeating: bool,
+}
+
+/// How long a key counts as held after a single press, covering the delay before the terminal
+/// starts repeating it.
+const HOLD_AFTER_PRESS: Duration = Duration::from_millis(550);
+/// How long a key counts as held after a repeated press.
+const HOLD_AFTER_REPEAT: Duration = Duration::from_millis(120);
+
+/// Terminal settings to put back when the program exits, since the global context is never
+/// dropped.
+static mut RESTORE_ON_EXIT: Option<termios> = None;
+static REGISTER_RESTORE_ON_EXIT: Once = Once::new();
+
+extern "C" fn restore_on_exit() {
+    #[allow(static_mut_refs)]
+    if let Some(settings) = unsafe { RESTORE_ON_EXIT.take() } {
+        restore_terminal(&settings);
+    }
+}
+
+fn restore_terminal(settings: &termios) {
+    unsafe { tcsetattr(STDIN_FILENO, TCSANOW, settings) };
+    // show the cursor, reset colours and leave the alternate screen
+    print!("\x1b[?25h\x1b[0m\x1b[?1049l");
+    let _ = stdout().flush();
+}
+
+impl TerminalBackend {
+    /// Take over the terminal, drawing frames of `width` by `height` pixels.
+    ///
+    /// # Errors
+    ///
+    /// If the standard input is not a terminal, an error is returned.
+    pub fn new(width: u32, height: u32) -> Result<Self> {
+        let mut settings: termios = unsafe { zeroed() };
+        if unsafe { tcgetattr(STDIN_FILENO, &raw mut settings) } == -1 {
+            return Err(Error::Io(std::io::Error::last_os_error()));
+        }
+        let original = settings;
+        // read keys as they are pressed, without echoing them or turning ctrl-c into a signal,
+        // and without waiting when none have been
+        settings.c_lflag &= !(ICANON | ECHO | ISIG);
+        settings.c_cc[VMIN] = 0;
+        settings.c_cc[VTIME] = 0;
+        unsafe {
+            tcsetattr(STDIN_FILENO, TCSANOW, &raw const settings);
+            RESTORE_ON_EXIT = Some(original);
+        }
+        REGISTER_RESTORE_ON_EXIT.call_once(|| unsafe {
+            atexit(restore_on_exit);
+        });
+        // enter the alternate screen and hide the cursor
+        print!("\x1b[?1049h\x1b[?25l");
+        Ok(Self {
+            width,
+            height,
+            terminal_size: (0, 0),
+            output: String::new(),
+            keys: [KeyState::default(); Input::COUNT],
+            quit_requested: false,
+        })
+    }
+
+    /// Read pending key presses from the terminal.
+    fn poll_input(&mut self) {
+        let mut bytes = [0; 64];
+        let Ok(count) = stdin().lock().read(&mut bytes) else {
+            return;
+        };
+        let now = Instant::now();
+        let mut bytes = &bytes[..count];
+        while let Some((&byte, rest)) = bytes.split_first() {
+            bytes = rest;
+            let input = match byte {
+                // ctrl-c
+                3 => {
+                    self.quit_requested = true;
+                    continue;
+                }
+                0x1b => match bytes {
+                    [b'[' | b'O', code, rest @ ..] => {
+                        bytes = rest;
+                        match code {
+                            b'A' => Input::Up,
+                            b'B' => Input::Down,
+                            b'C' => Input::Right,
+                            b'D' => Input::Left,
+                            _ => continue,
+                        }
+                    }
+                    _ => continue,
+                },
+                b'w' | b'W' => Input::Up,
+                b's' | b'S' => Input::Down,
+                b'a' | b'A' => Input::Left,
+                b'd' | b'D' => Input::Right,
+                b'z' | b'Z' => Input::A,
+                b'x' | b'X' => Input::B,
+                b'\r' | b'\n' => Input::Start,
+                b'\t' => Input::Hotkey,
+                _ => continue,
+            };
+            let key = &mut self.keys[input as usize];
+            key.repeating = key
+                .last_press
+                .is_some_and(|last_press| now - last_press < HOLD_AFTER_PRESS);
+            key.last_press = Some(now);
+        }
+    }
+
+    fn query_terminal_size() -> (u32, u32) {
+        let mut size: winsize = unsafe { zeroed() };
+        if unsafe { ioctl(stdout().as_raw_fd(), TIOCGWINSZ, &mut size) } == -1
+            || size.ws_col == 0
+            || size.ws_row == 0
+        {
+            return (80, 24);
+        }
+        (u32::from(size.ws_col), u32::from(size.ws_row))
+    }
+}
+
+impl Drop for TerminalBackend {
+    fn drop(&mut self) {
+        #[allow(static_mut_refs)]
+        if let Some(settings) = unsafe { RESTORE_ON_EXIT.take() } {
+            restore_terminal(&settings);
+        }
+    }
+}
+
+impl DisplayBackend for TerminalBackend {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::BGRX8888
+    }
+
+    fn present(&mut self, buffer: &[u8], stride: usize) -> Result<()> {
+        self.poll_input();
+        let terminal_size = Self::query_terminal_size();
+        self.output.clear();
+        if terminal_size != self.terminal_size {
+            self.terminal_size = terminal_size;
+            self.output.push_str("\x1b[0m\x1b[2J");
+        }
+        let (columns, rows) = terminal_size;
+        if self.width == 0 || self.height == 0 {
+            return Ok(());
+        }
+        // each cell is two pixels tall; shrink the frame to fit, keeping its aspect ratio
+        let scale = f64::from(columns) / f64::from(self.width);
+        let scale = scale.min(f64::from(rows * 2) / f64::from(self.height));
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let (out_width, out_height) = (
+            ((f64::from(self.width) * scale) as u32).clamp(1, columns),
+            ((f64::from(self.height) * scale) as u32).clamp(1, rows * 2),
+        );
+        let average = |left: u32, top: u32| {
+            let x0 = left * self.width / out_width;
+            let x1 = ((left + 1) * self.width / out_width).max(x0 + 1);
+            let y0 = top * self.height / out_height;
+            let y1 = ((top + 1) * self.height / out_height).max(y0 + 1);
+            let mut sum = [0_u32; 3];
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let start = y as usize * stride + x as usize * 4;
+                    let pixel = &buffer[start..start + 3];
+                    for (sum, value) in sum.iter_mut().zip(pixel) {
+                        *sum += u32::from(*value);
+                    }
+                }
+            }
+            let count = (y1 - y0) * (x1 - x0);
+            sum.map(|sum| sum / count)
+        };
+        let mut previous = None;
+        for row in 0..out_height.div_ceil(2) {
+            let _ = write!(self.output, "\x1b[{};1H", row + 1);
+            for column in 0..out_width {
+                let [top_blue, top_green, top_red] = average(column, row * 2);
+                let [bottom_blue, bottom_green, bottom_red] = if row * 2 + 1 < out_height {
+                    average(column, row * 2 + 1)
+                } else {
+                    [0; 3]
+                };
+                let colours = (
+                    (top_red, top_green, top_blue),
+                    (bottom_red, bottom_green, bottom_blue),
+                );
+                if previous != Some(colours) {
+                    let _ = write!(
+                        self.output,
+                        "\x1b[38;2;{top_red};{top_green};{top_blue}\
+                         ;48;2;{bottom_red};{bottom_green};{bottom_blue}m"
+                    );
+                    previous = Some(colours);
+                }
+                self.output.push('▀');
+            }
+        }
+        let mut stdout = stdout().lock();
+        stdout.write_all(self.output.as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    fn is_input_active(&mut self, input: Input) -> Option<bool> {
+        self.poll_input();
+        let key = self.keys[input as usize];
+        let hold = if key.repeating {
+            HOLD_AFTER_REPEAT
+        } else {
+            HOLD_AFTER_PRESS
+        };
+        Some(
+            key.last_press
+                .is_some_and(|last_press| last_press.elapsed() < hold),
+        )
+    }
+
+    fn is_quit_requested(&mut self) -> bool {
+        self.poll_input();
+        self.quit_requested
+    }
+}
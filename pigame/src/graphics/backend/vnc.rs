@@ -0,0 +1,608 @@
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+use strum::EnumCount;
+
+use crate::error::Result;
+use crate::graphics::Rotation;
+use crate::input::Input;
+
+use super::{DisplayBackend, PixelFormat, Region};
+
+/// Display backend serving frames to VNC clients over the RFB protocol.
+///
+/// Any number of clients can connect without a password; each is sent the parts of the frame
+/// that changed whenever it asks for an update. Keys pressed in a client drive
+/// [`is_active`](crate::input::is_active): arrow keys or WASD for the directions, Z and X for A
+/// and B, Enter for Start and Tab for Hotkey.
+///
+/// It can run on its own with [`new`](Self::new), or [`mirror`](Self::mirror) another backend so
+/// a game can be watched and played remotely while it is also shown on the device.
+pub struct VncBackend {
+    inner: Option<Box<dyn DisplayBackend>>,
+    width: u32,
+    height: u32,
+    pixel_format: PixelFormat,
+    address: SocketAddr,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Debug for VncBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VncBackend")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("pixel_format", &self.pixel_format)
+            .field("address", &self.address)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    clients: Vec<Client>,
+    next_id: u64,
+}
+
+struct Client {
+    id: u64,
+    /// Messages for the thread writing to the client, so a slow client never holds up the game.
+    updates: Sender<Vec<u8>>,
+    format: ClientFormat,
+    /// Whether the client is waiting for an update.
+    update_requested: bool,
+    /// Part of the frame changed since the client was last sent an update.
+    dirty: Option<Region>,
+    /// Inputs held by keys pressed in the client.
+    keys: [bool; Input::COUNT],
+}
+
+/// Pixel format requested by a client.
+#[derive(Debug, Clone, Copy)]
+struct ClientFormat {
+    bytes_per_pixel: usize,
+    big_endian: bool,
+    /// Maximum value and shift of the red, green and blue channels.
+    channels: [(u16, u8); 3],
+}
+
+impl ClientFormat {
+    /// The format advertised to clients until they ask for another: 32 bits per pixel,
+    /// little-endian, blue in the low byte.
+    const DEFAULT: Self = Self {
+        bytes_per_pixel: 4,
+        big_endian: false,
+        channels: [(255, 16), (255, 8), (255, 0)],
+    };
+
+    fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            bytes[0] = (self.bytes_per_pixel * 8) as u8;
+        }
+        bytes[1] = 24;
+        bytes[2] = u8::from(self.big_endian);
+        // true colour
+        bytes[3] = 1;
+        for (index, (max, shift)) in self.channels.into_iter().enumerate() {
+            bytes[4 + index * 2..6 + index * 2].copy_from_slice(&max.to_be_bytes());
+            bytes[10 + index] = shift;
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; 16]) -> Option<Self> {
+        let bytes_per_pixel = match bytes[0] {
+            8 => 1,
+            16 => 2,
+            32 => 4,
+            _ => return None,
+        };
+        // palette formats are not supported
+        if bytes[3] == 0 {
+            return None;
+        }
+        let channel = |index: usize| {
+            (
+                u16::from_be_bytes([bytes[4 + index * 2], bytes[5 + index * 2]]),
+                bytes[10 + index],
+            )
+        };
+        let channels = [channel(0), channel(1), channel(2)];
+        // every channel has to fit in the pixel once shifted into place
+        let bits = u32::from(bytes[0]);
+        if channels.iter().any(|&(max, shift)| {
+            u32::from(shift) >= bits || u32::from(shift) + u16::BITS - max.leading_zeros() > bits
+        }) {
+            return None;
+        }
+        Some(Self {
+            bytes_per_pixel,
+            big_endian: bytes[2] != 0,
+            channels,
+        })
+    }
+
+    fn write_pixel(self, [red, green, blue]: [u8; 3], output: &mut Vec<u8>) {
+        let mut value = 0_u32;
+        for ((max, shift), component) in self.channels.into_iter().zip([red, green, blue]) {
+            value |= (u32::from(component) * u32::from(max) / 255) << shift;
+        }
+        if self.big_endian {
+            output.extend_from_slice(&value.to_be_bytes()[4 - self.bytes_per_pixel..]);
+        } else {
+            output.extend_from_slice(&value.to_le_bytes()[..self.bytes_per_pixel]);
+        }
+    }
+}
+
+impl VncBackend {
+    /// Serve frames of `width` by `height` pixels to clients connecting to `address`, such as
+    /// `"127.0.0.1:5900"`.
+    ///
+    /// # Errors
+    ///
+    /// If the address cannot be listened on, an error is returned.
+    pub fn new<A: ToSocketAddrs>(width: u32, height: u32, address: A) -> Result<Self> {
+        Self::start(None, width, height, PixelFormat::BGRX8888, address)
+    }
+
+    /// Show frames on `inner` and also serve them to clients connecting to `address`.
+    ///
+    /// # Errors
+    ///
+    /// If the address cannot be listened on, an error is returned.
+    pub fn mirror<B: DisplayBackend + 'static, A: ToSocketAddrs>(
+        inner: B,
+        address: A,
+    ) -> Result<Self> {
+        let (width, height) = inner.size();
+        let pixel_format = inner.pixel_format();
+        Self::start(Some(Box::new(inner)), width, height, pixel_format, address)
+    }
+
+    fn start<A: ToSocketAddrs>(
+        inner: Option<Box<dyn DisplayBackend>>,
+        width: u32,
+        height: u32,
+        pixel_format: PixelFormat,
+        address: A,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        info!("serving vnc on {address}");
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let accepting = Arc::clone(&shared);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let shared = Arc::clone(&accepting);
+                thread::spawn(move || {
+                    if let Err(error) = serve(stream, &shared, width, height) {
+                        warn!("vnc client disconnected: {error}");
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            inner,
+            width,
+            height,
+            pixel_format,
+            address,
+            shared,
+        })
+    }
+
+    /// Address the server is listening on.
+    #[must_use]
+    pub const fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Copy the changed parts of the frame out for each client waiting for an update, to be
+    /// written by its own thread.
+    fn send_updates(&self, buffer: &[u8], stride: usize, regions: &[Region]) {
+        let mut shared = lock(&self.shared);
+        for client in &mut shared.clients {
+            for region in regions {
+                client.dirty = Some(client.dirty.map_or(*region, |dirty| dirty.union(*region)));
+            }
+            let Some(region) = client.dirty.filter(|_| client.update_requested) else {
+                continue;
+            };
+            let region = region.clip(self.width, self.height);
+            client.dirty = None;
+            client.update_requested = false;
+            let mut message = Vec::new();
+            self.encode_update(buffer, stride, region, client.format, &mut message);
+            // the writer only stops once the client has gone, which removes it here too
+            let _ = client.updates.send(message);
+        }
+    }
+
+    /// Build a framebuffer update message holding `region` in raw encoding.
+    fn encode_update(
+        &self,
+        buffer: &[u8],
+        stride: usize,
+        region: Region,
+        format: ClientFormat,
+        message: &mut Vec<u8>,
+    ) {
+        // framebuffer update, padding, one rectangle
+        message.extend([0, 0, 0, 1]);
+        #[allow(clippy::cast_possible_truncation)]
+        for value in [region.x, region.y, region.width, region.height] {
+            message.extend((value as u16).to_be_bytes());
+        }
+        // raw encoding
+        message.extend(0_i32.to_be_bytes());
+        let bytes_per_pixel = self.pixel_format.bytes_per_pixel();
+        for y in region.y as usize..region.bottom() as usize {
+            let row = &buffer[y * stride..];
+            for x in region.x as usize..region.right() as usize {
+                let colour = self
+                    .pixel_format
+                    .unpack(&row[x * bytes_per_pixel..(x + 1) * bytes_per_pixel]);
+                format.write_pixel([colour.red, colour.green, colour.blue], message);
+            }
+        }
+    }
+}
+
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Run the handshake with a new client, then handle its messages until it disconnects.
+fn serve(mut stream: TcpStream, shared: &Mutex<Shared>, width: u32, height: u32) -> io::Result<()> {
+    info!("vnc client connected from {}", stream.peer_addr()?);
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+    stream.write_all(b"RFB 003.008\n")?;
+    let mut version = [0; 12];
+    stream.read_exact(&mut version)?;
+    if &version[..8] == b"RFB 003." && version[10] < b'7' {
+        // 3.3 clients are told the security type rather than choosing it
+        stream.write_all(&1_u32.to_be_bytes())?;
+    } else {
+        // one security type, none
+        stream.write_all(&[1, 1])?;
+        let mut security_type = [0];
+        stream.read_exact(&mut security_type)?;
+        if version[10] >= b'8' {
+            stream.write_all(&0_u32.to_be_bytes())?;
+        }
+    }
+    // shared flag; every client shares the display anyway
+    let mut client_init = [0];
+    stream.read_exact(&mut client_init)?;
+    let name = b"pigame";
+    let mut server_init = Vec::new();
+    #[allow(clippy::cast_possible_truncation)]
+    {
+        server_init.extend((width as u16).to_be_bytes());
+        server_init.extend((height as u16).to_be_bytes());
+        server_init.extend(ClientFormat::DEFAULT.to_bytes());
+        server_init.extend((name.len() as u32).to_be_bytes());
+    }
+    server_init.extend(name);
+    stream.write_all(&server_init)?;
+
+    let (updates, pending) = channel::<Vec<u8>>();
+    let mut writer = stream.try_clone()?;
+    thread::spawn(move || {
+        for message in pending {
+            if let Err(error) = writer.write_all(&message) {
+                warn!("dropping vnc client: {error}");
+                // stop reading from the client too, which removes it
+                let _ = writer.shutdown(Shutdown::Both);
+                return;
+            }
+        }
+    });
+    let id = {
+        let mut shared = lock(shared);
+        let id = shared.next_id;
+        shared.next_id += 1;
+        shared.clients.push(Client {
+            id,
+            updates,
+            format: ClientFormat::DEFAULT,
+            update_requested: false,
+            dirty: Some(Region::new(0, 0, width, height)),
+            keys: [false; Input::COUNT],
+        });
+        id
+    };
+    let result = handle_messages(&mut stream, shared, id, width, height);
+    lock(shared).clients.retain(|client| client.id != id);
+    result
+}
+
+fn handle_messages(
+    stream: &mut TcpStream,
+    shared: &Mutex<Shared>,
+    id: u64,
+    width: u32,
+    height: u32,
+) -> io::Result<()> {
+    let with_client = |f: &mut dyn FnMut(&mut Client)| {
+        if let Some(client) = lock(shared)
+            .clients
+            .iter_mut()
+            .find(|client| client.id == id)
+        {
+            f(client);
+        }
+    };
+    loop {
+        let mut message_type = [0];
+        stream.read_exact(&mut message_type)?;
+        match message_type[0] {
+            // set pixel format
+            0 => {
+                let mut message = [0; 19];
+                stream.read_exact(&mut message)?;
+                let format = message[3..]
+                    .try_into()
+                    .ok()
+                    .and_then(ClientFormat::from_bytes);
+                if let Some(format) = format {
+                    with_client(&mut |client| client.format = format);
+                } else {
+                    warn!("vnc client asked for an unsupported pixel format");
+                }
+            }
+            // set encodings; only raw is ever sent
+            2 => {
+                let mut header = [0; 3];
+                stream.read_exact(&mut header)?;
+                let count = u16::from_be_bytes([header[1], header[2]]);
+                io::copy(&mut stream.take(u64::from(count) * 4), &mut io::sink())?;
+            }
+            // framebuffer update request
+            3 => {
+                let mut message = [0; 9];
+                stream.read_exact(&mut message)?;
+                let incremental = message[0] != 0;
+                with_client(&mut |client| {
+                    client.update_requested = true;
+                    if !incremental {
+                        client.dirty = Some(Region::new(0, 0, width, height));
+                    }
+                });
+            }
+            // key event
+            4 => {
+                let mut message = [0; 7];
+                stream.read_exact(&mut message)?;
+                let down = message[0] != 0;
+                let keysym = u32::from_be_bytes([message[3], message[4], message[5], message[6]]);
+                if let Some(input) = input_for_keysym(keysym) {
+                    with_client(&mut |client| client.keys[input as usize] = down);
+                }
+            }
+            // pointer event
+            5 => {
+                let mut message = [0; 5];
+                stream.read_exact(&mut message)?;
+            }
+            // client cut text
+            6 => {
+                let mut header = [0; 7];
+                stream.read_exact(&mut header)?;
+                let length = u32::from_be_bytes([header[3], header[4], header[5], header[6]]);
+                io::copy(&mut stream.take(u64::from(length)), &mut io::sink())?;
+            }
+            message_type => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown message type {message_type}"),
+                ))
+            }
+        }
+    }
+}
+
+const fn input_for_keysym(keysym: u32) -> Option<Input> {
+    Some(match keysym {
+        0xff51 | 0x61 | 0x41 => Input::Left,
+        0xff52 | 0x77 | 0x57 => Input::Up,
+        0xff53 | 0x64 | 0x44 => Input::Right,
+        0xff54 | 0x73 | 0x53 => Input::Down,
+        0x7a | 0x5a => Input::A,
+        0x78 | 0x58 => Input::B,
+        0xff0d => Input::Start,
+        0xff09 => Input::Hotkey,
+        _ => return None,
+    })
+}
+
+impl DisplayBackend for VncBackend {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    fn present(&mut self, buffer: &[u8], stride: usize) -> Result<()> {
+        if let Some(inner) = &mut self.inner {
+            inner.present(buffer, stride)?;
+        }
+        self.send_updates(
+            buffer,
+            stride,
+            &[Region::new(0, 0, self.width, self.height)],
+        );
+        Ok(())
+    }
+
+    fn present_regions(&mut self, buffer: &[u8], stride: usize, regions: &[Region]) -> Result<()> {
+        if let Some(inner) = &mut self.inner {
+            inner.present_regions(buffer, stride, regions)?;
+        }
+        self.send_updates(buffer, stride, regions);
+        Ok(())
+    }
+
+    fn rotation(&self) -> Rotation {
+        self.inner
+            .as_ref()
+            .map_or_else(Default::default, |inner| inner.rotation())
+    }
+
+    fn is_input_active(&mut self, input: Input) -> Option<bool> {
+        let shared = lock(&self.shared);
+        if shared
+            .clients
+            .iter()
+            .any(|client| client.keys[input as usize])
+        {
+            return Some(true);
+        }
+        drop(shared);
+        self.inner
+            .as_mut()
+            .map_or(Some(false), |inner| inner.is_input_active(input))
+    }
+
+    fn is_quit_requested(&mut self) -> bool {
+        self.inner
+            .as_mut()
+            .is_some_and(|inner| inner.is_quit_requested())
+    }
+
+    fn wait_for_vsync(&mut self) -> Result<()> {
+        self.inner
+            .as_mut()
+            .map_or(Ok(()), |inner| inner.wait_for_vsync())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    /// Connect a client to `backend` and run the handshake.
+    fn connect(backend: &VncBackend) -> TcpStream {
+        let mut stream = TcpStream::connect(backend.local_addr()).expect("the server is up");
+        let mut version = [0; 12];
+        stream
+            .read_exact(&mut version)
+            .expect("the server sends its version");
+        stream
+            .write_all(b"RFB 003.008\n")
+            .expect("the server is up");
+        let mut security_types = [0; 2];
+        stream
+            .read_exact(&mut security_types)
+            .expect("the server offers none");
+        // no security, shared
+        stream.write_all(&[1]).expect("the server is up");
+        let mut security_result = [0; 4];
+        stream
+            .read_exact(&mut security_result)
+            .expect("no security always passes");
+        stream.write_all(&[1]).expect("the server is up");
+        let mut server_init = [0; 30];
+        stream
+            .read_exact(&mut server_init)
+            .expect("the server describes the display");
+        stream
+    }
+
+    /// Wait up to a second for `condition` to hold.
+    fn eventually(mut condition: impl FnMut() -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(1) {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    #[test]
+    fn keys_held_by_a_client_are_released_when_it_disconnects() {
+        let mut backend = VncBackend::new(4, 4, "127.0.0.1:0").expect("any port will do");
+        let mut stream = connect(&backend);
+        // z held down
+        let mut key_event = [4, 1, 0, 0, 0, 0, 0, 0];
+        key_event[4..].copy_from_slice(&0x7a_u32.to_be_bytes());
+        stream.write_all(&key_event).expect("the server is up");
+        assert!(eventually(
+            || backend.is_input_active(Input::A) == Some(true)
+        ));
+        drop(stream);
+        assert!(eventually(
+            || backend.is_input_active(Input::A) == Some(false)
+        ));
+    }
+
+    #[test]
+    fn pixel_formats_that_do_not_fit_are_ignored() {
+        let mut backend = VncBackend::new(1, 1, "127.0.0.1:0").expect("any port will do");
+        let mut stream = connect(&backend);
+        stream
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .expect("the stream is open");
+        for (max, shift) in [(255, 40), (255, 28), (0, 32)] {
+            let mut set_pixel_format = [0; 20];
+            set_pixel_format[4..].copy_from_slice(&ClientFormat::DEFAULT.to_bytes());
+            set_pixel_format[8..10].copy_from_slice(&u16::to_be_bytes(max));
+            set_pixel_format[14] = shift;
+            stream
+                .write_all(&set_pixel_format)
+                .expect("the server is up");
+        }
+        // ask for the frame until it is sent in the format the client started with
+        let mut update = [0; 20];
+        assert!(eventually(|| {
+            stream
+                .write_all(&[3, 0, 0, 0, 0, 0, 0, 1, 0, 1])
+                .expect("the server is up");
+            backend
+                .present(&[1, 2, 3, 0], 4)
+                .expect("frames are only queued");
+            stream.read_exact(&mut update).is_ok()
+        }));
+        assert_eq!(update[16..], [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn clients_that_stop_reading_do_not_hold_up_frames() {
+        let mut backend = VncBackend::new(256, 256, "127.0.0.1:0").expect("any port will do");
+        let mut stream = connect(&backend);
+        let buffer = vec![0; 256 * 256 * 4];
+        let mut slowest = Duration::ZERO;
+        for _ in 0..64 {
+            // ask for the whole frame, then never read it
+            stream
+                .write_all(&[3, 0, 0, 0, 0, 0, 1, 0, 1, 0])
+                .expect("the server is up");
+            thread::sleep(Duration::from_millis(1));
+            let start = Instant::now();
+            backend
+                .present(&buffer, 256 * 4)
+                .expect("frames are only queued");
+            slowest = slowest.max(start.elapsed());
+        }
+        assert!(slowest < Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,403 @@
+use std::time::Duration;
+
+use glam::{IVec2, Vec2};
+use rand::random;
+
+use crate::context::get;
+
+use super::canvas::Canvas;
+use super::colour::Colour;
+use super::get_frame_time;
+
+/// View of a world larger than the screen, moving, zooming and turning everything drawn while it
+/// is set with [`set_camera`].
+///
+/// Drawing functions take positions in the world, which the camera maps onto the screen:
+/// `target` in the world is shown at `offset` on the screen, and the world is scaled up by `zoom`
+/// and turned by `rotation` around that point.
+///
+/// The camera can also shake the screen, by more the more trauma it has been given with
+/// [`add_trauma`](Self::add_trauma). Trauma wears off over time as
+/// [`update`](Self::update) is called each frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera2D {
+    /// Point in the world shown at `offset` on the screen.
+    pub target: Vec2,
+    /// Point on the screen `target` is shown at, which the camera zooms and turns around.
+    pub offset: Vec2,
+    /// Width in pixels on the screen of each pixel in the world; 1 if 0.
+    pub zoom: u32,
+    /// Angle in radians the world is turned clockwise by.
+    ///
+    /// Textures, sprites, tile maps, rectangles, lines and polygons are drawn turned. Circles,
+    /// ellipses, text and canvases are moved to where they should be but stay upright.
+    pub rotation: f32,
+    /// Furthest in pixels the screen moves when shaking with full trauma.
+    pub max_shake_offset: f32,
+    /// Furthest angle in radians the screen turns when shaking with full trauma.
+    pub max_shake_rotation: f32,
+    /// Trauma worn off each second.
+    pub trauma_decay: f32,
+    trauma: f32,
+    /// How far the screen is moved and turned by shaking this frame.
+    shake_offset: Vec2,
+    shake_rotation: f32,
+}
+
+impl Default for Camera2D {
+    fn default() -> Self {
+        Self::new(Vec2::ZERO, Vec2::ZERO)
+    }
+}
+
+impl Camera2D {
+    /// Create a camera showing the point `target` in the world at the point `offset` on the
+    /// screen, unzoomed and unturned.
+    ///
+    /// The screen shakes by up to 8 pixels, without turning, and loses all its trauma in a
+    /// second.
+    #[must_use]
+    pub const fn new(target: Vec2, offset: Vec2) -> Self {
+        Self {
+            target,
+            offset,
+            zoom: 1,
+            rotation: 0.,
+            max_shake_offset: 8.,
+            max_shake_rotation: 0.,
+            trauma_decay: 1.,
+            trauma: 0.,
+            shake_offset: Vec2::ZERO,
+            shake_rotation: 0.,
+        }
+    }
+
+    /// Set the zoom and return the camera.
+    #[must_use]
+    pub const fn with_zoom(mut self, zoom: u32) -> Self {
+        self.zoom = zoom;
+        self
+    }
+
+    /// Set the rotation and return the camera.
+    #[must_use]
+    pub const fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Set how far the screen moves and turns when shaking with full trauma, and return the
+    /// camera.
+    #[must_use]
+    pub const fn with_shake(mut self, max_offset: f32, max_rotation: f32) -> Self {
+        self.max_shake_offset = max_offset;
+        self.max_shake_rotation = max_rotation;
+        self
+    }
+
+    /// Add trauma, up to a total of 1, to shake the screen.
+    ///
+    /// The screen shakes by the square of the trauma, so small knocks barely register while
+    /// large ones are violent.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0., 1.);
+    }
+
+    /// Get the trauma the camera has, from 0 to 1.
+    #[must_use]
+    pub const fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Wear off trauma and pick how the screen shakes, for the time since the last frame from
+    /// [`get_frame_time`].
+    pub fn update(&mut self) {
+        self.advance(get_frame_time());
+    }
+
+    /// Wear off trauma for `time` and pick how the screen shakes.
+    pub fn advance(&mut self, time: Duration) {
+        self.trauma = self
+            .trauma_decay
+            .mul_add(-time.as_secs_f32(), self.trauma)
+            .max(0.);
+        let shake = self.trauma * self.trauma;
+        let noise = || random::<f32>().mul_add(2., -1.);
+        self.shake_offset = Vec2::new(noise(), noise()) * self.max_shake_offset * shake;
+        self.shake_rotation = self.max_shake_rotation * shake * noise();
+    }
+
+    /// Get where the point `point` in the world is shown on the screen.
+    #[must_use]
+    pub fn world_to_screen(&self, point: Vec2) -> Vec2 {
+        let turned = Vec2::from_angle(self.angle()).rotate((point - self.target) * self.scale());
+        self.offset + self.shake_offset + turned
+    }
+
+    /// Get the point in the world shown at `point` on the screen.
+    #[must_use]
+    pub fn screen_to_world(&self, point: Vec2) -> Vec2 {
+        let turned = point - self.offset - self.shake_offset;
+        self.target + Vec2::from_angle(-self.angle()).rotate(turned) / self.scale()
+    }
+
+    pub(super) fn zoom(&self) -> u32 {
+        self.zoom.max(1)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn scale(&self) -> f32 {
+        self.zoom() as f32
+    }
+
+    /// Angle the world is turned by, including shaking.
+    fn angle(&self) -> f32 {
+        self.rotation + self.shake_rotation
+    }
+
+    /// Whether the world is turned at all, so shapes cannot simply be moved and scaled.
+    pub(super) fn is_turned(&self) -> bool {
+        self.angle() != 0.
+    }
+
+    /// Where the top left corner of the world is on the screen when it is not turned, rounded
+    /// once so every shape is moved by the same whole number of pixels.
+    #[allow(clippy::cast_possible_truncation)]
+    fn translation(&self) -> IVec2 {
+        (self.offset + self.shake_offset - self.target * self.scale())
+            .round()
+            .as_ivec2()
+    }
+
+    /// Where the corner between pixels at `(x, y)` in the world is on the screen.
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    pub(super) fn corner_to_screen(&self, x: i32, y: i32) -> IVec2 {
+        if self.is_turned() {
+            return self
+                .world_to_screen(Vec2::new(x as f32, y as f32))
+                .round()
+                .as_ivec2();
+        }
+        let zoom = i32::try_from(self.zoom()).unwrap_or(i32::MAX);
+        self.translation()
+            .saturating_add(IVec2::new(x, y).saturating_mul(IVec2::splat(zoom)))
+    }
+
+    /// Get the pixel on the screen at the middle of the pixel at `(x, y)` in the world.
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    pub(super) fn pixel_to_screen(&self, x: i32, y: i32) -> IVec2 {
+        self.world_to_screen(Vec2::new(x as f32 + 0.5, y as f32 + 0.5))
+            .floor()
+            .as_ivec2()
+    }
+}
+
+/// Draw everything on the screen through `camera` until [`reset_camera`] is called.
+///
+/// Set the camera again after changing or updating it, as a copy is kept.
+pub fn set_camera(camera: &Camera2D) {
+    get().frame_buffer.canvas.set_camera(camera);
+}
+
+/// Draw on the screen in screen pixels again after [`set_camera`], such as for a HUD.
+pub fn reset_camera() {
+    get().frame_buffer.canvas.reset_camera();
+}
+
+impl Canvas {
+    /// Draw everything on the canvas through `camera` until [`reset_camera`](Self::reset_camera)
+    /// is called.
+    pub const fn set_camera(&mut self, camera: &Camera2D) {
+        self.camera = Some(*camera);
+    }
+
+    /// Draw on the canvas in its own pixels again.
+    pub const fn reset_camera(&mut self) {
+        self.camera = None;
+    }
+
+    /// Get the camera drawing goes through, if any.
+    #[must_use]
+    pub const fn camera(&self) -> Option<&Camera2D> {
+        self.camera.as_ref()
+    }
+
+    /// Get the camera if it turns the world, in which case shapes are drawn by moving their
+    /// points onto the canvas rather than pixel by pixel.
+    pub(super) fn turned_camera(&self) -> Option<Camera2D> {
+        self.camera.filter(Camera2D::is_turned)
+    }
+
+    /// Run `draw` with the camera lifted, for shapes already moved onto the canvas.
+    pub(super) fn without_camera(&mut self, draw: impl FnOnce(&mut Self)) {
+        let camera = self.camera.take();
+        draw(self);
+        self.camera = camera;
+    }
+
+    /// Get where the corner between pixels at `(x, y)` is on the canvas, through the camera.
+    pub(super) fn corner_to_canvas(&self, x: i32, y: i32) -> IVec2 {
+        self.camera
+            .map_or_else(|| IVec2::new(x, y), |camera| camera.corner_to_screen(x, y))
+    }
+
+    /// Get the zoom of the camera, or 1 without one.
+    pub(super) fn zoom(&self) -> u32 {
+        self.camera.map_or(1, |camera| camera.zoom())
+    }
+
+    /// Scale a length to canvas pixels by the zoom of the camera.
+    pub(super) fn zoom_length(&self, length: u32) -> u32 {
+        length.saturating_mul(self.zoom())
+    }
+
+    /// Draw `colour` over the pixels of row `y` from `left` to `right` inclusive, through a
+    /// camera that does not turn, so each covers a square of pixels on the canvas as wide as the
+    /// zoom.
+    pub(super) fn fill_world_span(&mut self, y: i32, left: i32, right: i32, colour: Colour) {
+        let Some(camera) = self.camera else {
+            self.fill_span(y, left, right, colour);
+            return;
+        };
+        if right < left {
+            return;
+        }
+        let top_left = camera.corner_to_screen(left, y);
+        let bottom_right = camera.corner_to_screen(right.saturating_add(1), y.saturating_add(1));
+        let height = i32::try_from(self.height).unwrap_or(i32::MAX);
+        for row in top_left.y.max(0)..bottom_right.y.min(height) {
+            self.fill_span(row, top_left.x, bottom_right.x.saturating_sub(1), colour);
+        }
+    }
+
+    /// Record that the pixels from `(left, top)` to `(right, bottom)` inclusive have been drawn
+    /// to, through a camera that does not turn.
+    pub(super) fn mark_dirty_world(&mut self, left: i32, top: i32, right: i32, bottom: i32) {
+        if right < left || bottom < top {
+            return;
+        }
+        let top_left = self.corner_to_canvas(left, top);
+        let bottom_right = self.corner_to_canvas(right.saturating_add(1), bottom.saturating_add(1));
+        self.mark_dirty_bounds(
+            top_left.x,
+            top_left.y,
+            bottom_right.x.saturating_sub(1),
+            bottom_right.y.saturating_sub(1),
+        );
+    }
+
+    /// Get the pixels that can appear on the canvas through the camera, from the top left to
+    /// the bottom right inclusive.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    pub(super) fn visible_bounds(&self) -> (i32, i32, i32, i32) {
+        let (width, height) = (i64::from(self.width), i64::from(self.height));
+        let (left, top, right, bottom) = match self.camera {
+            None => (0, 0, width - 1, height - 1),
+            Some(camera) if !camera.is_turned() => {
+                let zoom = i64::from(camera.zoom());
+                let translation = camera.translation().as_i64vec2();
+                (
+                    (-translation.x).div_euclid(zoom),
+                    (-translation.y).div_euclid(zoom),
+                    (width - 1 - translation.x).div_euclid(zoom),
+                    (height - 1 - translation.y).div_euclid(zoom),
+                )
+            }
+            Some(camera) => {
+                let (width, height) = (width as f32, height as f32);
+                let corners = [(0., 0.), (width, 0.), (0., height), (width, height)]
+                    .map(|(x, y)| camera.screen_to_world(Vec2::new(x, y)));
+                let min = corners.into_iter().reduce(Vec2::min).unwrap_or_default();
+                let max = corners.into_iter().reduce(Vec2::max).unwrap_or_default();
+                (
+                    min.x.floor() as i64,
+                    min.y.floor() as i64,
+                    max.x.ceil() as i64,
+                    max.y.ceil() as i64,
+                )
+            }
+        };
+        let clamp = |value: i64| value.clamp(i32::MIN.into(), i32::MAX.into()) as i32;
+        (clamp(left), clamp(top), clamp(right), clamp(bottom))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::super::colour::{BLACK, RED};
+    use super::super::testing::screen;
+    use super::super::{draw_rectangle, get_pixel};
+    use super::*;
+
+    /// Camera that moves, zooms and turns the world.
+    fn zoomed_and_turned() -> Camera2D {
+        Camera2D::new(Vec2::new(5., -3.), Vec2::new(8., 6.))
+            .with_zoom(3)
+            .with_rotation(0.7)
+    }
+
+    #[test]
+    fn screen_to_world_undoes_world_to_screen() {
+        let mut camera = zoomed_and_turned().with_shake(4., 0.3);
+        for trauma in [0., 0.5] {
+            camera.add_trauma(trauma);
+            camera.advance(Duration::ZERO);
+            for point in [(0., 0.), (5., -3.), (-12.5, 40.25), (1000., -700.)] {
+                let point = Vec2::from(point);
+                let back = camera.screen_to_world(camera.world_to_screen(point));
+                assert!(back.abs_diff_eq(point, 1e-3), "{point} came back as {back}");
+            }
+        }
+        // the target is shown at the offset
+        assert!(zoomed_and_turned()
+            .world_to_screen(Vec2::new(5., -3.))
+            .abs_diff_eq(Vec2::new(8., 6.), 1e-5));
+    }
+
+    #[test]
+    fn trauma_and_shaking_wear_off() {
+        let mut camera = zoomed_and_turned().with_shake(8., 0.5);
+        camera.add_trauma(0.75);
+        camera.add_trauma(0.75);
+        assert!((camera.trauma() - 1.).abs() < f32::EPSILON);
+        camera.advance(Duration::from_millis(250));
+        assert!((camera.trauma() - 0.75).abs() < 1e-6);
+        camera.advance(Duration::from_secs(1));
+        assert!(camera.trauma() == 0.);
+        let point = Vec2::new(3., 4.);
+        assert_eq!(
+            camera.world_to_screen(point),
+            zoomed_and_turned().world_to_screen(point)
+        );
+    }
+
+    #[test]
+    fn set_camera_moves_and_zooms_drawing() {
+        let _screen = screen();
+        set_camera(&Camera2D::new(Vec2::new(1., 1.), Vec2::new(4., 5.)).with_zoom(2));
+        draw_rectangle(1, 1, 1, 1, RED);
+        reset_camera();
+        draw_rectangle(0, 0, 1, 1, RED);
+        for (x, y) in [(4, 5), (5, 5), (4, 6), (5, 6), (0, 0)] {
+            assert_eq!(get_pixel(x, y), Some(RED), "({x}, {y})");
+        }
+        for (x, y) in [(3, 5), (6, 5), (4, 7), (1, 1), (1, 0)] {
+            assert_eq!(get_pixel(x, y), Some(BLACK), "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn set_camera_turns_drawing() {
+        let _screen = screen();
+        // a quarter turn clockwise about (5, 5)
+        set_camera(&Camera2D::new(Vec2::ZERO, Vec2::new(5., 5.)).with_rotation(FRAC_PI_2));
+        draw_rectangle(0, 0, 2, 1, RED);
+        assert_eq!(get_pixel(4, 5), Some(RED));
+        assert_eq!(get_pixel(4, 6), Some(RED));
+        assert_eq!(get_pixel(5, 5), Some(BLACK));
+        assert_eq!(get_pixel(4, 7), Some(BLACK));
+        assert_eq!(get_pixel(4, 4), Some(BLACK));
+    }
+}
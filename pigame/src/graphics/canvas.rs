@@ -0,0 +1,495 @@
+use std::fmt::{self, Debug, Formatter};
+use std::ops::Range;
+
+use glam::IVec2;
+
+use crate::context::get;
+
+use super::backend::{PixelFormat, Region};
+use super::camera::Camera2D;
+use super::colour::{BlendMode, Colour};
+use super::palette::{Palette, PixelEncoding};
+
+/// Image that can be drawn into and then drawn onto other canvases or the screen.
+///
+/// The screen is itself a canvas; the drawing functions in [`graphics`](super) draw to it.
+/// Drawing things that rarely change, like backgrounds, into a canvas once and drawing the canvas
+/// every frame saves redrawing them piece by piece.
+#[derive(Clone)]
+pub struct Canvas {
+    pub(crate) buffer: Vec<u8>,
+    pub(crate) pixel_format: PixelFormat,
+    /// Bytes from the start of one row of `buffer` to the start of the next.
+    pub(crate) stride: usize,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) blend_mode: BlendMode,
+    /// Camera drawing positions go through, if any.
+    pub(crate) camera: Option<Camera2D>,
+    /// Palette the pixels pick from if the canvas is indexed, with one byte per pixel.
+    pub(crate) palette: Option<Box<Palette>>,
+    /// Regions drawn to since the last frame, for the screen.
+    pub(crate) damage: Vec<Region>,
+    /// Whether the whole canvas must be presented next frame.
+    pub(crate) fully_damaged: bool,
+    pub(crate) track_damage: bool,
+}
+
+impl Debug for Canvas {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Canvas")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("pixel_format", &self.pixel_format)
+            .field("camera", &self.camera)
+            .field("indexed", &self.palette.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Canvas {
+    /// Create a canvas of `width` by `height` pixels in the pixel format of the display, filled
+    /// with black.
+    #[must_use]
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::with_pixel_format(width, height, get().frame_buffer.canvas.pixel_format)
+    }
+
+    /// Create a canvas of `width` by `height` pixels in `pixel_format`, filled with black.
+    ///
+    /// Canvases in the pixel format of the display are the quickest to draw onto the screen.
+    /// Formats with an alpha channel, like [`PixelFormat::BGRA8888`], start out transparent and
+    /// keep the transparency of what is drawn into them when they are drawn elsewhere.
+    #[must_use]
+    pub fn with_pixel_format(width: u32, height: u32, pixel_format: PixelFormat) -> Self {
+        let stride = width as usize * pixel_format.bytes_per_pixel();
+        Self {
+            buffer: vec![0; stride * height as usize],
+            pixel_format,
+            stride,
+            width,
+            height,
+            blend_mode: BlendMode::Alpha,
+            camera: None,
+            palette: None,
+            damage: Vec::new(),
+            fully_damaged: false,
+            track_damage: false,
+        }
+    }
+
+    /// Get the width of the canvas.
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the canvas.
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the format pixels are stored in, or converted to from the palette if the canvas is
+    /// indexed.
+    #[must_use]
+    pub const fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    /// Get how drawing combines colours with what is already on the canvas.
+    #[must_use]
+    pub const fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    /// Set how drawing combines colours with what is already on the canvas; alpha blending by
+    /// default.
+    pub const fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    /// Number of bytes used to store a single pixel in `buffer`.
+    pub(crate) const fn bytes_per_pixel(&self) -> usize {
+        if self.palette.is_some() {
+            1
+        } else {
+            self.pixel_format.bytes_per_pixel()
+        }
+    }
+
+    /// Byte range of the pixel at `(x, y)` in `buffer`.
+    pub(crate) const fn pixel_range(&self, x: usize, y: usize) -> Range<usize> {
+        let bytes_per_pixel = self.bytes_per_pixel();
+        let start = y * self.stride + x * bytes_per_pixel;
+        start..start + bytes_per_pixel
+    }
+
+    /// Record that a region of the canvas has been drawn to.
+    pub(crate) fn mark_dirty(&mut self, region: Region) {
+        if !self.track_damage || self.fully_damaged {
+            return;
+        }
+        let mut region = region.clip(self.width, self.height);
+        if region.is_empty() {
+            return;
+        }
+        while let Some(index) = self.damage.iter().position(|other| other.touches(region)) {
+            region = region.union(self.damage.swap_remove(index));
+        }
+        self.damage.push(region);
+        if self.damage.len() > MAX_DIRTY_REGIONS {
+            let bounds = self.damage.drain(..).reduce(Region::union);
+            self.damage.extend(bounds);
+        }
+    }
+
+    /// Record that the pixels from `(left, top)` to `(right, bottom)` inclusive have been drawn
+    /// to; the corners may lie outside the canvas.
+    pub(crate) fn mark_dirty_bounds(&mut self, left: i32, top: i32, right: i32, bottom: i32) {
+        if right < 0 || bottom < 0 || right < left || bottom < top {
+            return;
+        }
+        let (left, top) = (left.max(0).unsigned_abs(), top.max(0).unsigned_abs());
+        self.mark_dirty(Region::new(
+            left,
+            top,
+            right.unsigned_abs() + 1 - left,
+            bottom.unsigned_abs() + 1 - top,
+        ));
+    }
+
+    /// Draw `colour` over the pixels of row `y` from `left` to `right` inclusive, cutting off any
+    /// part outside the canvas.
+    pub(crate) fn fill_span(&mut self, y: i32, left: i32, right: i32, colour: Colour) {
+        let Ok(y) = u32::try_from(y) else {
+            return;
+        };
+        let Some(last) = self.width.checked_sub(1) else {
+            return;
+        };
+        if y >= self.height || right < 0 || right < left || colour.alpha == 0 {
+            return;
+        }
+        let left = left.max(0).unsigned_abs();
+        let right = right.unsigned_abs().min(last);
+        if left > right {
+            return;
+        }
+        let start = self.pixel_range(left as usize, y as usize).start;
+        let end = self.pixel_range(right as usize, y as usize).end;
+        let encoding = PixelEncoding::new(self.pixel_format, self.palette.as_deref());
+        let blend_mode = self.blend_mode;
+        let bytes_per_pixel = encoding.bytes_per_pixel();
+        let span = self.buffer[start..end].chunks_exact_mut(bytes_per_pixel);
+        if colour.alpha == u8::MAX && blend_mode == BlendMode::Alpha {
+            let pixel = encoding.pack(colour);
+            for slice in span {
+                slice.copy_from_slice(&pixel[..bytes_per_pixel]);
+            }
+        } else {
+            for slice in span {
+                let blended = colour.blend(encoding.unpack(slice), blend_mode);
+                slice.copy_from_slice(&encoding.pack(blended)[..bytes_per_pixel]);
+            }
+        }
+    }
+
+    /// Record that the whole canvas has been drawn to.
+    pub(crate) fn mark_all_dirty(&mut self) {
+        self.damage.clear();
+        self.fully_damaged = true;
+    }
+
+    /// Fill the canvas with a colour, replacing what was there whatever the blend mode.
+    pub fn clear(&mut self, colour: Colour) {
+        self.mark_all_dirty();
+        let encoding = self.encoding();
+        let pixel = encoding.pack(colour);
+        let bytes_per_pixel = encoding.bytes_per_pixel();
+        for slice in self.buffer.chunks_exact_mut(bytes_per_pixel) {
+            slice.copy_from_slice(&pixel[..bytes_per_pixel]);
+        }
+    }
+
+    /// Draw a rectangle on the canvas with its top left corner at `(x, y)`.
+    ///
+    /// Parts falling outside the canvas are cut off, and nothing is drawn unless `w` and `h` are
+    /// positive.
+    pub fn draw_rectangle(&mut self, x: i32, y: i32, w: i32, h: i32, colour: Colour) {
+        if w <= 0 || h <= 0 {
+            return;
+        }
+        if let Some(camera) = self.turned_camera() {
+            let (right, bottom) = (x.saturating_add(w), y.saturating_add(h));
+            let corners = [(x, y), (right, y), (right, bottom), (x, bottom)]
+                .map(|(x, y)| camera.corner_to_screen(x, y));
+            self.without_camera(|canvas| canvas.draw_polygon(&corners, colour));
+            return;
+        }
+        let right = x.saturating_add(w - 1);
+        let bottom = y.saturating_add(h - 1);
+        self.mark_dirty_world(x, y, right, bottom);
+        let (_, first_row, _, last_row) = self.visible_bounds();
+        for row in y.max(first_row)..=bottom.min(last_row) {
+            self.fill_world_span(row, x, right, colour);
+        }
+    }
+
+    /// Draw the outline of a rectangle on the canvas with its top left corner at `(x, y)`,
+    /// `thickness` pixels wide inside its edges. Outlines too thick to leave a hole are drawn
+    /// filled.
+    ///
+    /// Parts falling outside the canvas are cut off, and nothing is drawn unless `w` and `h` are
+    /// positive.
+    pub fn draw_rectangle_lines(
+        &mut self,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+        thickness: u32,
+        colour: Colour,
+    ) {
+        if w <= 0 || h <= 0 || thickness == 0 {
+            return;
+        }
+        let thickness = i32::try_from(thickness).unwrap_or(i32::MAX);
+        if thickness.saturating_mul(2) >= w.min(h) {
+            self.draw_rectangle(x, y, w, h, colour);
+            return;
+        }
+        let inner = h.saturating_sub(thickness.saturating_mul(2));
+        let (right, bottom) = (
+            x.saturating_add(w - thickness),
+            y.saturating_add(h - thickness),
+        );
+        let below_top = y.saturating_add(thickness);
+        self.draw_rectangle(x, y, w, thickness, colour);
+        self.draw_rectangle(x, bottom, w, thickness, colour);
+        self.draw_rectangle(x, below_top, thickness, inner, colour);
+        self.draw_rectangle(right, below_top, thickness, inner, colour);
+    }
+
+    /// Read back the colour of a pixel.
+    ///
+    /// Returns `None` if the position is outside the canvas.
+    #[must_use]
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<Colour> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let bytes = self.buffer.get(self.pixel_range(x as usize, y as usize))?;
+        Some(self.encoding().unpack(bytes))
+    }
+
+    /// Draw all of `source` onto the canvas with its top left corner at `(x, y)`, blended using
+    /// the blend mode of this canvas.
+    ///
+    /// Parts falling outside the canvas are cut off. A camera moves `source` but does not zoom or
+    /// turn it.
+    pub fn draw_canvas(&mut self, source: &Self, x: i32, y: i32) {
+        let IVec2 { x, y } = self.corner_to_canvas(x, y);
+        // the part of the canvas covered, in its own coordinates
+        let left = i64::from(x).max(0);
+        let top = i64::from(y).max(0);
+        let right = (i64::from(x) + i64::from(source.width)).min(i64::from(self.width));
+        let bottom = (i64::from(y) + i64::from(source.height)).min(i64::from(self.height));
+        if left >= right || top >= bottom {
+            return;
+        }
+        // everything is now between 0 and the size of one of the canvases
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let [left, top, right, bottom, source_left, source_top] = [
+            left,
+            top,
+            right,
+            bottom,
+            left - i64::from(x),
+            top - i64::from(y),
+        ]
+        .map(|value| value as usize);
+        #[allow(clippy::cast_possible_truncation)]
+        self.mark_dirty(Region::new(
+            left as u32,
+            top as u32,
+            (right - left) as u32,
+            (bottom - top) as u32,
+        ));
+        let encoding = PixelEncoding::new(self.pixel_format, self.palette.as_deref());
+        let source_encoding = source.encoding();
+        let bytes_per_pixel = encoding.bytes_per_pixel();
+        let source_bytes_per_pixel = source_encoding.bytes_per_pixel();
+        let length = right - left;
+        // whether pixels can be copied across without blending
+        let opaque = (source.palette.is_some() || !source.pixel_format.has_alpha())
+            && self.blend_mode == BlendMode::Alpha;
+        let same_encoding = match (&self.palette, &source.palette) {
+            (None, None) => source.pixel_format == self.pixel_format,
+            (Some(palette), Some(source_palette)) => palette == source_palette,
+            _ => false,
+        };
+        for row in 0..bottom - top {
+            let start = self.pixel_range(left, top + row).start;
+            let destination = &mut self.buffer[start..start + length * bytes_per_pixel];
+            let start = source.pixel_range(source_left, source_top + row).start;
+            let source_row = &source.buffer[start..start + length * source_bytes_per_pixel];
+            if opaque && same_encoding {
+                destination.copy_from_slice(source_row);
+                continue;
+            }
+            for (pixel, source_pixel) in destination
+                .chunks_exact_mut(bytes_per_pixel)
+                .zip(source_row.chunks_exact(source_bytes_per_pixel))
+            {
+                let mut colour = source_encoding.unpack(source_pixel);
+                if !opaque {
+                    colour = colour.blend(encoding.unpack(pixel), self.blend_mode);
+                }
+                pixel.copy_from_slice(&encoding.pack(colour)[..bytes_per_pixel]);
+            }
+        }
+    }
+
+    /// Get the contents of the canvas as RGBA bytes, 4 per pixel, row by row from the top left.
+    /// Pixels are opaque unless the pixel format has an alpha channel.
+    #[must_use]
+    pub fn to_rgba(&self) -> Vec<u8> {
+        if self.stride == 0 {
+            return Vec::new();
+        }
+        let encoding = self.encoding();
+        let bytes_per_pixel = encoding.bytes_per_pixel();
+        let row_length = self.width as usize * bytes_per_pixel;
+        self.buffer
+            .chunks_exact(self.stride)
+            .flat_map(|row| row[..row_length].chunks_exact(bytes_per_pixel))
+            .flat_map(|pixel| <[u8; 4]>::from(encoding.unpack(pixel)))
+            .collect()
+    }
+}
+
+/// Dirty regions kept apart before they are merged into their bounding box.
+const MAX_DIRTY_REGIONS: usize = 16;
+
+#[cfg(test)]
+mod tests {
+    use super::super::colour::{BLACK, BLUE, RED};
+    use super::super::testing::{screen, SIZE};
+    use super::super::{draw_canvas, draw_rectangle, draw_rectangle_lines, get_pixel};
+    use super::*;
+
+    /// Canvas 3 by 2 pixels with a red top row and a blue bottom row.
+    fn stripes() -> Canvas {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.draw_rectangle(0, 0, 3, 1, RED);
+        canvas.draw_rectangle(0, 1, 3, 1, BLUE);
+        canvas
+    }
+
+    #[test]
+    fn draw_rectangle_at_negative_coordinates_keeps_the_part_on_screen() {
+        let _screen = screen();
+        draw_rectangle(-2, -3, 4, 5, RED);
+        assert_eq!(get_pixel(0, 0), Some(RED));
+        assert_eq!(get_pixel(1, 1), Some(RED));
+        assert_eq!(get_pixel(2, 0), Some(BLACK));
+        assert_eq!(get_pixel(0, 2), Some(BLACK));
+    }
+
+    #[test]
+    fn draw_rectangle_reaching_the_limits_fills_the_screen() {
+        let _screen = screen();
+        draw_rectangle(i32::MIN, i32::MIN, i32::MAX, i32::MAX, RED);
+        assert_eq!(get_pixel(0, 0), Some(BLACK));
+        draw_rectangle(-1, -1, i32::MAX, i32::MAX, RED);
+        assert_eq!(get_pixel(0, 0), Some(RED));
+        assert_eq!(get_pixel(SIZE.0 - 1, SIZE.1 - 1), Some(RED));
+    }
+
+    #[test]
+    fn draw_rectangle_lines_leaves_the_middle_empty() {
+        let _screen = screen();
+        draw_rectangle_lines(1, 1, 6, 5, 2, RED);
+        for (x, y) in [
+            (1, 1),
+            (6, 1),
+            (1, 5),
+            (6, 5),
+            (2, 3),
+            (5, 3),
+            (3, 2),
+            (4, 4),
+        ] {
+            assert_eq!(get_pixel(x, y), Some(RED), "({x}, {y})");
+        }
+        for (x, y) in [(3, 3), (4, 3), (0, 0), (7, 3), (3, 6)] {
+            assert_eq!(get_pixel(x, y), Some(BLACK), "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn draw_rectangle_lines_too_thick_for_a_hole_is_filled() {
+        let _screen = screen();
+        draw_rectangle_lines(0, 0, 4, 6, 2, RED);
+        assert_eq!(get_pixel(1, 3), Some(RED));
+        assert_eq!(get_pixel(2, 3), Some(RED));
+        assert_eq!(get_pixel(4, 3), Some(BLACK));
+    }
+
+    #[test]
+    fn draw_rectangle_lines_reaching_the_limits_keeps_its_edges() {
+        let _screen = screen();
+        draw_rectangle_lines(-1, -1, i32::MAX, i32::MAX, 2, RED);
+        draw_rectangle_lines(
+            i32::MAX - 4,
+            i32::MAX - 4,
+            i32::MAX,
+            i32::MAX,
+            u32::MAX / 4,
+            RED,
+        );
+        assert_eq!(get_pixel(0, 0), Some(RED));
+        assert_eq!(get_pixel(1, 1), Some(BLACK));
+        assert_eq!(get_pixel(SIZE.0 - 1, SIZE.1 - 1), Some(BLACK));
+    }
+
+    #[test]
+    fn draw_canvas_above_and_left_of_the_screen_keeps_its_bottom_right() {
+        let _screen = screen();
+        draw_canvas(&stripes(), -2, -1);
+        assert_eq!(get_pixel(0, 0), Some(BLUE));
+        assert_eq!(get_pixel(1, 0), Some(BLACK));
+        assert_eq!(get_pixel(0, 1), Some(BLACK));
+    }
+
+    #[test]
+    fn draw_canvas_past_the_edge_keeps_its_top_left() {
+        let _screen = screen();
+        let (right, bottom) = (SIZE.0 - 1, SIZE.1 - 1);
+        #[allow(clippy::cast_possible_wrap)]
+        draw_canvas(&stripes(), right as i32, bottom as i32);
+        assert_eq!(get_pixel(right, bottom), Some(RED));
+        assert_eq!(get_pixel(right - 1, bottom), Some(BLACK));
+        assert_eq!(get_pixel(right, bottom - 1), Some(BLACK));
+    }
+
+    #[test]
+    fn draw_canvas_off_the_screen_draws_nothing() {
+        let _screen = screen();
+        for (x, y) in [
+            (-3, 0),
+            (0, -2),
+            (i32::MAX, 0),
+            (0, i32::MAX),
+            (i32::MIN, i32::MIN),
+        ] {
+            draw_canvas(&stripes(), x, y);
+        }
+        for y in 0..SIZE.1 {
+            for x in 0..SIZE.0 {
+                assert_eq!(get_pixel(x, y), Some(BLACK), "({x}, {y})");
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::{fmt::Display, ops::Mul};
 
-/// A colour struct with red, green, and blue components
-#[derive(Debug, Clone, Copy)]
+/// A colour struct with red, green, blue, and alpha components
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Colour {
     /// Red component; 0-255 inclusive
     pub red: u8,
@@ -9,6 +9,8 @@ pub struct Colour {
     pub green: u8,
     /// Blue component; 0-255 inclusive
     pub blue: u8,
+    /// Alpha (opacity) component; 0 is fully transparent, 255 fully opaque
+    pub alpha: u8,
 }
 
 /// Create a new colour with the given red, green, and blue components
@@ -17,34 +19,117 @@ pub const fn colour(red: u8, green: u8, blue: u8) -> Colour {
     Colour::new(red, green, blue)
 }
 
+/// Create a new colour with the given red, green, blue, and alpha components
+#[must_use]
+pub const fn rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Colour {
+    Colour::rgba(red, green, blue, alpha)
+}
+
 impl Colour {
-    /// Create a new colour with the given red, green, and blue components
+    /// Create a new opaque colour with the given red, green, and blue components
     #[must_use]
     pub const fn new(red: u8, green: u8, blue: u8) -> Self {
-        Self { red, green, blue }
+        Self::rgba(red, green, blue, u8::MAX)
+    }
+
+    /// Create a new colour with the given red, green, blue, and alpha components
+    #[must_use]
+    pub const fn rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Self {
+        Self {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+
+    /// Return the same colour with a different alpha component
+    #[must_use]
+    pub const fn with_alpha(self, alpha: u8) -> Self {
+        Self { alpha, ..self }
     }
 
-    /// Convert the colour to a hex string (e.g. "#FF0000")
+    /// Convert the colour to a hex string (e.g. "#FF0000"), with the alpha component on the end
+    /// unless the colour is opaque (e.g. "#FF000080")
     #[must_use]
     pub fn to_hex(self) -> String {
-        format!("#{:02X}{:02X}{:02X}", self.red, self.green, self.blue)
+        let hex = format!("#{:02X}{:02X}{:02X}", self.red, self.green, self.blue);
+        if self.alpha == u8::MAX {
+            hex
+        } else {
+            format!("{hex}{:02X}", self.alpha)
+        }
     }
 
-    /// Create a colour from a hex string (e.g. "#FF0000" or "FF0000")
+    /// Create a colour from a hex string (e.g. "#FF0000" or "FF0000"), optionally followed by
+    /// an alpha component (e.g. "#FF000080")
     #[must_use]
     pub fn from_hex(hex: &str) -> Option<Self> {
         let hex = hex.trim_start_matches('#');
-        let red = u8::from_str_radix(&hex[0..2], 16).ok()?;
-        let green = u8::from_str_radix(&hex[2..4], 16).ok()?;
-        let blue = u8::from_str_radix(&hex[4..6], 16).ok()?;
-        Some(colour(red, green, blue))
+        let component = |index: usize| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok();
+        let alpha = match hex.len() {
+            6 => u8::MAX,
+            8 => component(6)?,
+            _ => return None,
+        };
+        Some(rgba(component(0)?, component(2)?, component(4)?, alpha))
     }
 
-    pub(crate) const fn to_bgra_bytes(self) -> [u8; 4] {
-        [self.blue, self.green, self.red, 0]
+    /// Draw this colour over `destination` using `mode`.
+    #[must_use]
+    pub fn blend(self, destination: Self, mode: BlendMode) -> Self {
+        let alpha = self.alpha;
+        let inverse = u8::MAX - alpha;
+        // how much of the destination shows through, and the opacity of the result
+        let remaining = mul(destination.alpha, inverse);
+        let result_alpha = alpha + remaining;
+        if result_alpha == 0 {
+            return TRANSPARENT;
+        }
+        let channel = |source: u8, destination: u8| match mode {
+            BlendMode::Alpha => {
+                let sum = u32::from(source) * u32::from(alpha)
+                    + u32::from(destination) * u32::from(remaining);
+                #[allow(clippy::cast_possible_truncation)]
+                {
+                    ((sum + u32::from(result_alpha) / 2) / u32::from(result_alpha)) as u8
+                }
+            }
+            BlendMode::Additive => destination.saturating_add(mul(source, alpha)),
+            BlendMode::Multiply => mul(destination, mul(source, alpha) + inverse),
+        };
+        rgba(
+            channel(self.red, destination.red),
+            channel(self.green, destination.green),
+            channel(self.blue, destination.blue),
+            result_alpha,
+        )
     }
 }
 
+/// Multiply two components as if they were fractions of 255.
+#[allow(clippy::cast_possible_truncation)]
+const fn mul(a: u8, b: u8) -> u8 {
+    let product = a as u32 * b as u32 + 128;
+    ((product + (product >> 8)) >> 8) as u8
+}
+
+/// How drawing combines colours with what is already there.
+///
+/// In every mode the alpha component of the colour being drawn scales its effect, so drawing a
+/// fully transparent colour changes nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// Paint over what is there, letting it show through in proportion to transparency.
+    #[default]
+    Alpha,
+    /// Add to what is there, brightening it; good for light, fire and sparks.
+    Additive,
+    /// Multiply with what is there, darkening it; good for shadows and tinting.
+    Multiply,
+}
+
+/// Converts to `0xRRGGBB`, dropping the alpha component.
 impl From<Colour> for u32 {
     fn from(colour: Colour) -> Self {
         Self::from(colour.red) << 16 | Self::from(colour.green) << 8 | Self::from(colour.blue)
@@ -81,6 +166,18 @@ impl From<(u8, u8, u8)> for Colour {
     }
 }
 
+impl From<[u8; 4]> for Colour {
+    fn from([red, green, blue, alpha]: [u8; 4]) -> Self {
+        rgba(red, green, blue, alpha)
+    }
+}
+
+impl From<Colour> for [u8; 4] {
+    fn from(colour: Colour) -> Self {
+        [colour.red, colour.green, colour.blue, colour.alpha]
+    }
+}
+
 impl Mul<f32> for Colour {
     type Output = Self;
 
@@ -90,10 +187,11 @@ impl Mul<f32> for Colour {
             clippy::cast_sign_loss,
             clippy::cast_lossless
         )]
-        colour(
+        rgba(
             (self.red as f32 * rhs) as u8,
             (self.green as f32 * rhs) as u8,
             (self.blue as f32 * rhs) as u8,
+            self.alpha,
         )
     }
 }
@@ -109,6 +207,75 @@ mod consts {
     pub const YELLOW: Colour = colour(255, 255, 0);
     pub const WHITE: Colour = colour(255, 255, 255);
     pub const BLACK: Colour = colour(0, 0, 0);
+    pub const TRANSPARENT: Colour = super::rgba(0, 0, 0, 0);
 }
 
 pub use consts::*;
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::screen;
+    use super::super::{draw_rectangle, get_pixel, set_blend_mode};
+    use super::*;
+
+    #[test]
+    fn alpha_blending_mixes_in_proportion_to_opacity() {
+        let half_red = rgba(255, 0, 0, 128);
+        assert_eq!(
+            half_red.blend(BLACK, BlendMode::Alpha),
+            rgba(128, 0, 0, 255)
+        );
+        assert_eq!(RED.blend(BLUE, BlendMode::Alpha), RED);
+        assert_eq!(
+            half_red.blend(TRANSPARENT, BlendMode::Alpha),
+            rgba(255, 0, 0, 128)
+        );
+    }
+
+    #[test]
+    fn additive_blending_brightens_up_to_white() {
+        let grey = colour(100, 100, 100);
+        assert_eq!(
+            grey.blend(colour(200, 50, 0), BlendMode::Additive),
+            colour(255, 150, 100)
+        );
+        assert_eq!(
+            grey.with_alpha(128)
+                .blend(colour(200, 50, 0), BlendMode::Additive),
+            colour(250, 100, 50)
+        );
+    }
+
+    #[test]
+    fn multiply_blending_darkens() {
+        let grey = colour(200, 200, 200);
+        assert_eq!(
+            colour(128, 255, 0).blend(grey, BlendMode::Multiply),
+            colour(100, 200, 0)
+        );
+        assert_eq!(BLACK.with_alpha(0).blend(grey, BlendMode::Multiply), grey);
+    }
+
+    #[test]
+    fn transparent_colours_change_nothing() {
+        let destination = colour(10, 20, 30);
+        for mode in [BlendMode::Alpha, BlendMode::Additive, BlendMode::Multiply] {
+            assert_eq!(WHITE.with_alpha(0).blend(destination, mode), destination);
+        }
+    }
+
+    #[test]
+    fn drawing_on_the_screen_uses_the_blend_mode() {
+        let _screen = screen();
+        draw_rectangle(0, 0, 2, 1, colour(100, 0, 0));
+        set_blend_mode(BlendMode::Additive);
+        draw_rectangle(0, 0, 1, 1, colour(100, 50, 0));
+        set_blend_mode(BlendMode::Multiply);
+        draw_rectangle(1, 0, 1, 1, colour(128, 255, 255));
+        set_blend_mode(BlendMode::Alpha);
+        draw_rectangle(2, 0, 1, 1, WHITE.with_alpha(128));
+        assert_eq!(get_pixel(0, 0), Some(colour(200, 50, 0)));
+        assert_eq!(get_pixel(1, 0), Some(colour(50, 0, 0)));
+        assert_eq!(get_pixel(2, 0), Some(colour(128, 128, 128)));
+    }
+}
@@ -0,0 +1,319 @@
+use glam::Vec2;
+
+use crate::context::get;
+
+use super::backend::Channel;
+use super::camera::Camera2D;
+use super::canvas::Canvas;
+use super::colour::Colour;
+
+/// Thresholds for ordered dithering, spreading the rounding of each colour over a 4 by 4 block
+/// of pixels.
+const BAYER: [[f32; 4]; 4] = [
+    [0., 8., 2., 10.],
+    [12., 4., 14., 6.],
+    [3., 11., 1., 9.],
+    [15., 7., 13., 5.],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Shape {
+    Linear { angle: f32 },
+    Radial,
+}
+
+/// Colours blending smoothly into each other across a rectangle or the screen.
+///
+/// Each stop is a position along the gradient from 0 to 1 and the colour there, with the colours
+/// in between blended, including their alpha. Before the first stop and after the last the
+/// colour stays the same.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    shape: Shape,
+    stops: Vec<(f32, Colour)>,
+    /// Whether to use ordered dithering, trading banding where the display cannot show every
+    /// colour in between, such as with 16 bits per pixel, for a fine pattern.
+    pub dither: bool,
+}
+
+impl Gradient {
+    /// Create a gradient running in a straight line across a rectangle, at `angle` radians
+    /// clockwise from left to right, from its first stop at one corner to its last at the
+    /// opposite corner.
+    #[must_use]
+    pub fn linear(angle: f32, stops: &[(f32, Colour)]) -> Self {
+        Self::new(Shape::Linear { angle }, stops)
+    }
+
+    /// Create a gradient running out from the middle of a rectangle, with its first stop in the
+    /// middle and its last in an ellipse touching the middle of each side.
+    #[must_use]
+    pub fn radial(stops: &[(f32, Colour)]) -> Self {
+        Self::new(Shape::Radial, stops)
+    }
+
+    fn new(shape: Shape, stops: &[(f32, Colour)]) -> Self {
+        let mut stops = stops.to_vec();
+        stops.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        Self {
+            shape,
+            stops,
+            dither: false,
+        }
+    }
+
+    /// Set whether to use ordered dithering, and return the gradient.
+    #[must_use]
+    pub const fn with_dither(mut self, dither: bool) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// Get the colour `position` along the gradient, or transparent black if it has no stops.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn colour_at(&self, position: f32) -> Colour {
+        let [red, green, blue, alpha] = self.blend_at(position).map(|value| value.round() as u8);
+        Colour::rgba(red, green, blue, alpha)
+    }
+
+    /// Get the colour `position` along the gradient, with components from 0 to 255 not yet
+    /// rounded.
+    fn blend_at(&self, position: f32) -> [f32; 4] {
+        let components =
+            |colour: Colour| [colour.red, colour.green, colour.blue, colour.alpha].map(f32::from);
+        let next = self.stops.partition_point(|(stop, _)| *stop <= position);
+        match (next.checked_sub(1), self.stops.get(next)) {
+            (Some(previous), Some(&(end, to))) => {
+                let (start, from) = self.stops[previous];
+                let along = (position - start) / (end - start);
+                let (from, to) = (components(from), components(to));
+                [0, 1, 2, 3].map(|index| (to[index] - from[index]).mul_add(along, from[index]))
+            }
+            (Some(last), None) => components(self.stops[last].1),
+            (None, Some(&(_, first))) => components(first),
+            (None, None) => [0.; 4],
+        }
+    }
+
+    /// Get how far along the gradient the point `(x, y)` from the top left of a rectangle
+    /// `width` by `height` is.
+    fn position(&self, x: f32, y: f32, width: f32, height: f32) -> f32 {
+        let (x, y) = (x - width / 2., y - height / 2.);
+        match self.shape {
+            Shape::Linear { angle } => {
+                let (sin, cos) = angle.sin_cos();
+                // distance from the middle to the corners furthest along the line
+                let half = width.mul_add(cos.abs(), height * sin.abs()) / 2.;
+                if half == 0. {
+                    return 0.;
+                }
+                x.mul_add(cos, y * sin) / half / 2. + 0.5
+            }
+            Shape::Radial => (Vec2::new(x, y) / Vec2::new(width, height) * 2.).length(),
+        }
+    }
+}
+
+/// Draw a rectangle on the screen with its top left corner at `(x, y)`, filled with `gradient`.
+///
+/// Parts falling outside the screen are cut off, and nothing is drawn unless `w` and `h` are
+/// positive.
+pub fn draw_rectangle_gradient(x: i32, y: i32, w: i32, h: i32, gradient: &Gradient) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_rectangle_gradient(x, y, w, h, gradient);
+}
+
+/// Clear the screen to `gradient`, replacing what was there whatever the blend mode.
+pub fn clear_background_gradient(gradient: &Gradient) {
+    get().frame_buffer.canvas.clear_gradient(gradient);
+}
+
+impl Canvas {
+    /// Draw a rectangle on the canvas with its top left corner at `(x, y)`, filled with
+    /// `gradient`. A camera that turns the world turns the gradient with it.
+    ///
+    /// Parts falling outside the canvas are cut off, and nothing is drawn unless `w` and `h` are
+    /// positive.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn draw_rectangle_gradient(&mut self, x: i32, y: i32, w: i32, h: i32, gradient: &Gradient) {
+        if w <= 0 || h <= 0 || gradient.stops.is_empty() {
+            return;
+        }
+        if let Some(camera) = self.turned_camera() {
+            self.draw_rectangle_gradient_turned(x, y, w, h, gradient, &camera);
+            return;
+        }
+        let right = x.saturating_add(w - 1);
+        let bottom = y.saturating_add(h - 1);
+        self.mark_dirty_world(x, y, right, bottom);
+        let (first_column, first_row, last_column, last_row) = self.visible_bounds();
+        let (width, height) = (w as f32, h as f32);
+        for row in y.max(first_row)..=bottom.min(last_row) {
+            for column in x.max(first_column)..=right.min(last_column) {
+                let position = gradient.position(
+                    (column - x) as f32 + 0.5,
+                    (row - y) as f32 + 0.5,
+                    width,
+                    height,
+                );
+                let colour = self.gradient_colour(gradient, position, column, row);
+                self.fill_world_span(row, column, column, colour);
+            }
+        }
+    }
+
+    /// Fill the canvas with `gradient`, replacing what was there whatever the blend mode.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn clear_gradient(&mut self, gradient: &Gradient) {
+        self.mark_all_dirty();
+        let (width, height) = (self.width as f32, self.height as f32);
+        let bytes_per_pixel = self.bytes_per_pixel();
+        for row in 0..self.height {
+            for column in 0..self.width {
+                let position =
+                    gradient.position(column as f32 + 0.5, row as f32 + 0.5, width, height);
+                #[allow(clippy::cast_possible_wrap)]
+                let colour = self.gradient_colour(gradient, position, column as i32, row as i32);
+                let pixel = self.encoding().pack(colour);
+                let range = self.pixel_range(column as usize, row as usize);
+                self.buffer[range].copy_from_slice(&pixel[..bytes_per_pixel]);
+            }
+        }
+    }
+
+    /// Draw a rectangle filled with `gradient`, turned by `camera`, by finding the point in the
+    /// world at the middle of each pixel it covers.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    fn draw_rectangle_gradient_turned(
+        &mut self,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+        gradient: &Gradient,
+        camera: &Camera2D,
+    ) {
+        let (x, y, width, height) = (x as f32, y as f32, w as f32, h as f32);
+        let corners = [
+            (x, y),
+            (x + width, y),
+            (x, y + height),
+            (x + width, y + height),
+        ]
+        .map(|(x, y)| camera.world_to_screen(Vec2::new(x, y)));
+        let min = corners.into_iter().reduce(Vec2::min).unwrap_or_default();
+        let max = corners.into_iter().reduce(Vec2::max).unwrap_or_default();
+        let (left, top) = (min.x.floor() as i32, min.y.floor() as i32);
+        let (right, bottom) = (
+            (max.x.ceil() as i32).saturating_sub(1),
+            (max.y.ceil() as i32).saturating_sub(1),
+        );
+        self.mark_dirty_bounds(left, top, right, bottom);
+        let last_column = i32::try_from(self.width).unwrap_or(i32::MAX) - 1;
+        let last_row = i32::try_from(self.height).unwrap_or(i32::MAX) - 1;
+        for row in top.max(0)..=bottom.min(last_row) {
+            for column in left.max(0)..=right.min(last_column) {
+                let world = camera.screen_to_world(Vec2::new(column as f32, row as f32) + 0.5);
+                let (along, down) = (world.x - x, world.y - y);
+                if !(0. ..width).contains(&along) || !(0. ..height).contains(&down) {
+                    continue;
+                }
+                let position = gradient.position(along, down, width, height);
+                let colour = self.gradient_colour(gradient, position, column, row);
+                self.fill_span(row, column, column, colour);
+            }
+        }
+    }
+
+    /// Get the colour of `gradient` at `position`, dithered for the pixel at `(x, y)` into the
+    /// colours the canvas can hold if the gradient asks for it.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn gradient_colour(&self, gradient: &Gradient, position: f32, x: i32, y: i32) -> Colour {
+        if !gradient.dither {
+            return gradient.colour_at(position);
+        }
+        let [red, green, blue, alpha] = gradient.blend_at(position);
+        let threshold = (BAYER[y.rem_euclid(4) as usize][x.rem_euclid(4) as usize] + 0.5) / 16.;
+        let format = self.pixel_format;
+        Colour::rgba(
+            dither(red, format.red, threshold),
+            dither(green, format.green, threshold),
+            dither(blue, format.blue, threshold),
+            alpha.round() as u8,
+        )
+    }
+}
+
+/// Round `value` from 0 to 255 up or down to one of the levels `channel` can hold, rounding up
+/// more often the closer it is to the level above and the higher `threshold` is.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn dither(value: f32, channel: Channel, threshold: f32) -> u8 {
+    // the gap between levels, as the lowest bits are dropped when packed
+    let step = (1_u32 << (8 - channel.length.clamp(1, 8))) as f32;
+    ((value / step + threshold).floor() * step).clamp(0., 255.) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::camera::set_camera;
+    use super::super::colour::{rgba, BLACK, BLUE, RED};
+    use super::super::get_pixel;
+    use super::super::testing::{screen, SIZE};
+    use super::*;
+
+    #[test]
+    fn colour_at_blends_between_stops() {
+        let gradient = Gradient::linear(0., &[(1., BLUE), (0., RED)]);
+        assert_eq!(gradient.colour_at(-1.), RED);
+        assert_eq!(gradient.colour_at(0.5), rgba(128, 0, 128, 255));
+        assert_eq!(gradient.colour_at(2.), BLUE);
+        assert_eq!(Gradient::radial(&[]).colour_at(0.5), rgba(0, 0, 0, 0));
+    }
+
+    #[test]
+    fn draw_rectangle_gradient_runs_from_the_first_stop_to_the_last() {
+        let _screen = screen();
+        let gradient = Gradient::linear(0., &[(0., RED), (1., BLUE)]);
+        draw_rectangle_gradient(0, 0, 4, 2, &gradient);
+        let row: Vec<_> = (0..5).filter_map(|x| get_pixel(x, 1)).collect();
+        assert_eq!(
+            row,
+            [
+                rgba(223, 0, 32, 255),
+                rgba(159, 0, 96, 255),
+                rgba(96, 0, 159, 255),
+                rgba(32, 0, 223, 255),
+                BLACK,
+            ]
+        );
+        assert_eq!(get_pixel(0, 2), Some(BLACK));
+    }
+
+    #[test]
+    fn clear_background_gradient_fills_the_screen() {
+        let _screen = screen();
+        clear_background_gradient(&Gradient::radial(&[(0., RED), (1., BLUE)]));
+        let (middle_x, middle_y) = (SIZE.0 / 2, SIZE.1 / 2);
+        let middle = get_pixel(middle_x, middle_y).expect("the middle is on the screen");
+        let corner = get_pixel(0, 0).expect("the corner is on the screen");
+        assert!(middle.red > 200 && middle.blue < 55);
+        assert_eq!(corner, BLUE);
+    }
+
+    #[test]
+    fn turned_gradients_far_off_the_screen_draw_nothing() {
+        let _screen = screen();
+        let gradient = Gradient::linear(0., &[(0., RED), (1., BLUE)]);
+        set_camera(&Camera2D::default().with_rotation(0.5));
+        draw_rectangle_gradient(i32::MIN, i32::MIN, 4, 4, &gradient);
+        draw_rectangle_gradient(i32::MAX, i32::MAX, 4, 4, &gradient);
+        assert_eq!(get_pixel(0, 0), Some(BLACK));
+    }
+}
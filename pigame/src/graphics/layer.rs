@@ -0,0 +1,115 @@
+use crate::context::get;
+
+use super::camera::Camera2D;
+use super::colour::BlendMode;
+
+/// Drawing put off until the end of the frame, with the camera and blend mode it was asked for
+/// with.
+pub(crate) struct Deferred {
+    layer: i32,
+    camera: Option<Camera2D>,
+    blend_mode: BlendMode,
+    draw: Box<dyn FnOnce()>,
+}
+
+/// Put off drawing until [`next_frame`](super::next_frame), then run `draw` with the other
+/// drawing on the screen sorted by `layer`, lowest first.
+///
+/// `draw` calls the usual drawing functions, so the background and the HUD can be drawn in
+/// whatever order is convenient, as long as each is given the right layer. Drawing on the same
+/// layer happens in the order it was asked for, and drawing that is not put off happens
+/// straight away, beneath every layer. The camera and blend mode set when this is called are used
+/// for `draw`, whatever they are by the end of the frame.
+///
+/// `draw` has to own what it draws, such as text formatted into a `String` or a texture shared
+/// with an [`Rc`](std::rc::Rc).
+pub fn draw_on_layer(layer: i32, draw: impl FnOnce() + 'static) {
+    let frame_buffer = &mut get().frame_buffer;
+    frame_buffer.deferred.push(Deferred {
+        layer,
+        camera: frame_buffer.canvas.camera,
+        blend_mode: frame_buffer.canvas.blend_mode,
+        draw: Box::new(draw),
+    });
+}
+
+/// Draw everything put off with [`draw_on_layer`] now, rather than waiting for
+/// [`next_frame`](super::next_frame), such as before taking a screenshot.
+///
+/// Drawing put off while the layers are being drawn is drawn after them.
+pub fn flush_layers() {
+    loop {
+        let mut deferred = std::mem::take(&mut get().frame_buffer.deferred);
+        if deferred.is_empty() {
+            return;
+        }
+        deferred.sort_by_key(|deferred| deferred.layer);
+        let canvas = &get().frame_buffer.canvas;
+        let (camera, blend_mode) = (canvas.camera, canvas.blend_mode);
+        for deferred in deferred {
+            let canvas = &mut get().frame_buffer.canvas;
+            canvas.camera = deferred.camera;
+            canvas.blend_mode = deferred.blend_mode;
+            (deferred.draw)();
+        }
+        let canvas = &mut get().frame_buffer.canvas;
+        canvas.camera = camera;
+        canvas.blend_mode = blend_mode;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::super::camera::{reset_camera, set_camera};
+    use super::super::colour::{colour, BLACK, BLUE, GREEN, RED, YELLOW};
+    use super::super::testing::screen;
+    use super::super::{draw_rectangle, get_pixel, next_frame, set_blend_mode};
+    use super::*;
+
+    #[test]
+    fn layers_are_drawn_lowest_first_and_in_order_within_a_layer() {
+        let _screen = screen();
+        draw_on_layer(2, || draw_rectangle(0, 0, 1, 1, BLUE));
+        draw_on_layer(1, || draw_rectangle(0, 0, 2, 1, RED));
+        draw_on_layer(1, || draw_rectangle(1, 0, 1, 1, GREEN));
+        draw_on_layer(-5, || draw_rectangle(2, 0, 1, 1, RED));
+        // drawn straight away, so beneath every layer
+        draw_rectangle(2, 0, 1, 1, YELLOW);
+        assert_eq!(get_pixel(0, 0), Some(BLACK));
+        flush_layers();
+        assert_eq!(get_pixel(0, 0), Some(BLUE));
+        assert_eq!(get_pixel(1, 0), Some(GREEN));
+        assert_eq!(get_pixel(2, 0), Some(RED));
+    }
+
+    #[test]
+    fn layers_use_the_camera_and_blend_mode_they_were_asked_for_with() {
+        let _screen = screen();
+        let moved = Camera2D::new(Vec2::ZERO, Vec2::new(3., 2.));
+        draw_rectangle(3, 2, 1, 1, colour(100, 0, 0));
+        set_camera(&moved);
+        set_blend_mode(BlendMode::Additive);
+        draw_on_layer(0, || draw_rectangle(0, 0, 1, 1, colour(50, 0, 0)));
+        reset_camera();
+        set_blend_mode(BlendMode::Multiply);
+        flush_layers();
+        assert_eq!(get_pixel(3, 2), Some(colour(150, 0, 0)));
+        let canvas = &get().frame_buffer.canvas;
+        assert_eq!(canvas.camera, None);
+        assert_eq!(canvas.blend_mode, BlendMode::Multiply);
+    }
+
+    #[test]
+    fn drawing_put_off_while_flushing_is_drawn_after() {
+        let _screen = screen();
+        draw_on_layer(1, || {
+            draw_on_layer(0, || draw_rectangle(0, 0, 1, 1, GREEN));
+            draw_rectangle(0, 0, 1, 1, RED);
+        });
+        next_frame().expect("the headless display cannot fail");
+        assert_eq!(get_pixel(0, 0), Some(GREEN));
+        assert!(get().frame_buffer.deferred.is_empty());
+    }
+}
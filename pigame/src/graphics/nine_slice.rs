@@ -0,0 +1,303 @@
+use std::path::Path;
+
+use crate::context::get;
+use crate::error::Result;
+
+use super::backend::Region;
+use super::canvas::Canvas;
+use super::texture::{DrawTextureParams, Texture};
+
+/// How the edges or centre of a nine-slice panel fill the space between its corners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SliceFill {
+    /// Stretched to fit.
+    #[default]
+    Stretch,
+    /// Repeated at their own size, with the last copy cut off.
+    Tile,
+}
+
+/// Image cut into nine pieces by two lines across and two lines down, for framed boxes of any
+/// size such as menus and dialogs.
+///
+/// When drawn, the corners keep their size, the edges fill the space between the corners along
+/// their length and the centre fills the rest, each stretched or tiled.
+#[derive(Debug, Clone)]
+pub struct NineSlice {
+    texture: Texture,
+    source: Region,
+    left: u32,
+    top: u32,
+    right: u32,
+    bottom: u32,
+    /// How the edges fill the space between the corners.
+    pub edges: SliceFill,
+    /// How the centre fills the space inside the edges.
+    pub centre: SliceFill,
+}
+
+impl NineSlice {
+    /// Cut all of `texture` into corners and edges `left`, `top`, `right` and `bottom` pixels
+    /// wide, with stretched edges and centre.
+    #[must_use]
+    pub const fn new(texture: Texture, left: u32, top: u32, right: u32, bottom: u32) -> Self {
+        let source = Region::new(0, 0, texture.width(), texture.height());
+        Self {
+            texture,
+            source,
+            left,
+            top,
+            right,
+            bottom,
+            edges: SliceFill::Stretch,
+            centre: SliceFill::Stretch,
+        }
+    }
+
+    /// Load a nine-slice panel from a PNG, BMP or QOI image file. See [`new`](Self::new).
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or is not a supported image, an error is returned.
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+        left: u32,
+        top: u32,
+        right: u32,
+        bottom: u32,
+    ) -> Result<Self> {
+        Ok(Self::new(
+            Texture::from_file(path)?,
+            left,
+            top,
+            right,
+            bottom,
+        ))
+    }
+
+    /// Cut the panel from `source` of the texture rather than all of it, such as for a panel in
+    /// a texture atlas, and return the panel.
+    #[must_use]
+    pub const fn with_source(mut self, source: Region) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set how the edges and centre fill the panel, and return the panel.
+    #[must_use]
+    pub const fn with_fill(mut self, edges: SliceFill, centre: SliceFill) -> Self {
+        self.edges = edges;
+        self.centre = centre;
+        self
+    }
+
+    /// Get the image the panel is cut from.
+    #[must_use]
+    pub const fn texture(&self) -> &Texture {
+        &self.texture
+    }
+}
+
+/// Draw `nine_slice` on the screen filling the rectangle with its top left corner at `(x, y)`.
+///
+/// Nothing is drawn unless `w` and `h` are positive.
+pub fn draw_nine_slice(nine_slice: &NineSlice, x: i32, y: i32, w: i32, h: i32) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_nine_slice(nine_slice, x, y, w, h);
+}
+
+impl Canvas {
+    /// Draw `nine_slice` on the canvas filling the rectangle with its top left corner at
+    /// `(x, y)`.
+    ///
+    /// Corners are shrunk to fit if the rectangle is smaller than them, and nothing is drawn
+    /// unless `w` and `h` are positive.
+    pub fn draw_nine_slice(&mut self, nine_slice: &NineSlice, x: i32, y: i32, w: i32, h: i32) {
+        let (Ok(width), Ok(height)) = (u32::try_from(w), u32::try_from(h)) else {
+            return;
+        };
+        let texture = &nine_slice.texture;
+        let source = nine_slice.source.clip(texture.width(), texture.height());
+        if width == 0 || height == 0 || source.is_empty() {
+            return;
+        }
+        let columns = slices(
+            source.x,
+            source.width,
+            nine_slice.left,
+            nine_slice.right,
+            width,
+        );
+        let rows = slices(
+            source.y,
+            source.height,
+            nine_slice.top,
+            nine_slice.bottom,
+            height,
+        );
+        for (row, &(source_y, source_height, dy, dest_height)) in rows.iter().enumerate() {
+            for (column, &(source_x, source_width, dx, dest_width)) in columns.iter().enumerate() {
+                let fill = match (row, column) {
+                    (1, 1) => nine_slice.centre,
+                    (1, _) | (_, 1) => nine_slice.edges,
+                    _ => SliceFill::Stretch,
+                };
+                // edges only repeat along their length, and are stretched across it
+                let tile = (
+                    fill == SliceFill::Tile && column == 1,
+                    fill == SliceFill::Tile && row == 1,
+                );
+                self.draw_slice(
+                    texture,
+                    Region::new(source_x, source_y, source_width, source_height),
+                    (x.saturating_add_unsigned(dx), y.saturating_add_unsigned(dy)),
+                    (dest_width, dest_height),
+                    tile,
+                );
+            }
+        }
+    }
+
+    /// Fill `size` at `(x, y)` with `source` of `texture`, repeating it at its own size across
+    /// and down if `tile` says so and stretching it otherwise.
+    fn draw_slice(
+        &mut self,
+        texture: &Texture,
+        source: Region,
+        (x, y): (i32, i32),
+        (width, height): (u32, u32),
+        (tile_x, tile_y): (bool, bool),
+    ) {
+        if source.is_empty() || width == 0 || height == 0 {
+            return;
+        }
+        let step_x = if tile_x { source.width } else { width };
+        let step_y = if tile_y { source.height } else { height };
+        for dy in (0..height).step_by(step_y as usize) {
+            let piece_height = step_y.min(height - dy);
+            for dx in (0..width).step_by(step_x as usize) {
+                let piece_width = step_x.min(width - dx);
+                let params = DrawTextureParams {
+                    source: Some(Region::new(
+                        source.x,
+                        source.y,
+                        if tile_x { piece_width } else { source.width },
+                        if tile_y { piece_height } else { source.height },
+                    )),
+                    dest_size: Some((piece_width, piece_height)),
+                    ..DrawTextureParams::default()
+                };
+                self.draw_texture_ex(
+                    texture,
+                    x.saturating_add_unsigned(dx),
+                    y.saturating_add_unsigned(dy),
+                    params,
+                );
+            }
+        }
+    }
+}
+
+/// Split `size` pixels into the start, middle and end of a nine-slice panel, cut from
+/// `source_size` pixels at `source_start` with ends `start` and `end` pixels long.
+///
+/// Returns where each part starts in the source, its length there, and where it starts and its
+/// length when drawn. Ends longer than `size` together are shrunk in proportion.
+#[allow(clippy::cast_possible_truncation)]
+fn slices(
+    source_start: u32,
+    source_size: u32,
+    start: u32,
+    end: u32,
+    size: u32,
+) -> [(u32, u32, u32, u32); 3] {
+    let start = start.min(source_size);
+    let end = end.min(source_size - start);
+    let (drawn_start, drawn_end) = if start + end <= size {
+        (start, end)
+    } else {
+        let drawn_start = (u64::from(start) * u64::from(size) / u64::from(start + end)) as u32;
+        (drawn_start, size - drawn_start)
+    };
+    let middle = source_size - start - end;
+    let drawn_middle = size - drawn_start - drawn_end;
+    [
+        (source_start, start, 0, drawn_start),
+        (source_start + start, middle, drawn_start, drawn_middle),
+        (
+            source_start + start + middle,
+            end,
+            drawn_start + drawn_middle,
+            drawn_end,
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::colour::{Colour, BLACK, BLUE, GREEN, RED};
+    use super::super::get_pixel;
+    use super::super::testing::screen;
+    use super::*;
+
+    /// Panel 3 by 3 pixels with red corners, blue edges and a green centre, cut 1 pixel in.
+    fn panel() -> NineSlice {
+        let colours = [RED, BLUE, RED, BLUE, GREEN, BLUE, RED, BLUE, RED];
+        let rgba: Vec<u8> = colours
+            .iter()
+            .flat_map(|colour| [colour.red, colour.green, colour.blue, colour.alpha])
+            .collect();
+        let texture = Texture::from_rgba(3, 3, &rgba).expect("there is a pixel for each byte");
+        NineSlice::new(texture, 1, 1, 1, 1)
+    }
+
+    fn row(y: u32, width: u32) -> Vec<Option<Colour>> {
+        (0..width).map(|x| get_pixel(x, y)).collect()
+    }
+
+    #[test]
+    fn draw_nine_slice_keeps_the_corners_and_stretches_the_rest() {
+        let _screen = screen();
+        draw_nine_slice(&panel(), 0, 0, 5, 4);
+        let edge = [RED, BLUE, BLUE, BLUE, RED, BLACK].map(Some);
+        let middle = [BLUE, GREEN, GREEN, GREEN, BLUE, BLACK].map(Some);
+        assert_eq!(row(0, 6), edge);
+        assert_eq!(row(1, 6), middle);
+        assert_eq!(row(2, 6), middle);
+        assert_eq!(row(3, 6), edge);
+        assert_eq!(row(4, 6), [Some(BLACK); 6]);
+    }
+
+    #[test]
+    fn draw_nine_slice_shrinks_corners_that_do_not_fit() {
+        let _screen = screen();
+        draw_nine_slice(&panel(), 1, 1, 2, 1);
+        assert_eq!(row(1, 4), [BLACK, RED, RED, BLACK].map(Some));
+        assert_eq!(get_pixel(1, 2), Some(BLACK));
+    }
+
+    #[test]
+    fn draw_nine_slice_tiles_from_a_source_in_an_atlas() {
+        let _screen = screen();
+        // a strip below a row of black, with a middle two pixels long
+        let mut rgba = vec![0; 16];
+        for colour in [RED, BLUE, GREEN, RED] {
+            rgba.extend([colour.red, colour.green, colour.blue, colour.alpha]);
+        }
+        let texture = Texture::from_rgba(4, 2, &rgba).expect("there is a pixel for each byte");
+        let strip = NineSlice::new(texture, 1, 0, 1, 0).with_source(Region::new(0, 1, 4, 1));
+        draw_nine_slice(&strip, 0, 0, 7, 1);
+        let strip = strip.with_fill(SliceFill::Tile, SliceFill::Tile);
+        draw_nine_slice(&strip, 0, 1, 7, 1);
+        assert_eq!(
+            row(0, 7),
+            [RED, BLUE, BLUE, BLUE, GREEN, GREEN, RED].map(Some)
+        );
+        assert_eq!(
+            row(1, 7),
+            [RED, BLUE, GREEN, BLUE, GREEN, BLUE, RED].map(Some)
+        );
+    }
+}
@@ -0,0 +1,422 @@
+use std::cell::Cell;
+use std::fmt::{self, Debug, Formatter};
+use std::ops::RangeInclusive;
+
+use crate::context::get;
+
+use super::backend::{PixelFormat, Region};
+use super::canvas::Canvas;
+use super::colour::{Colour, BLACK};
+
+/// Number of colours [`Palette::nearest`] remembers the answer for.
+const NEAREST_CACHE_SIZE: usize = 4096;
+
+/// Table of 256 colours that the pixels of an indexed canvas pick from by number.
+///
+/// Changing a colour in the palette changes every pixel drawn with it, which makes flashes,
+/// colour cycling and swapping between palettes, such as for day and night, as cheap as
+/// changing the table. The alpha of the colours is ignored, so indexed pixels are always opaque.
+#[derive(Clone)]
+pub struct Palette {
+    colours: [Colour; 256],
+    /// Colours recently looked up by [`nearest`](Self::nearest), in slots picked by hashing
+    /// them, so that drawing many pixels of a few colours does not search the table for each.
+    nearest: NearestCache,
+}
+
+impl PartialEq for Palette {
+    fn eq(&self, other: &Self) -> bool {
+        self.colours == other.colours
+    }
+}
+
+impl Eq for Palette {}
+
+impl Debug for Palette {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Palette")
+            .field("colours", &self.colours)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+impl Palette {
+    /// Create a palette starting with `colours`, with the rest of the 256 entries black.
+    /// Colours past the 256th are ignored.
+    #[must_use]
+    pub fn new(colours: &[Colour]) -> Self {
+        let mut palette = [BLACK; 256];
+        for (entry, colour) in palette.iter_mut().zip(colours) {
+            *entry = *colour;
+        }
+        Self {
+            colours: palette,
+            nearest: NearestCache::new(),
+        }
+    }
+
+    /// Get all 256 colours.
+    #[must_use]
+    pub const fn colours(&self) -> &[Colour; 256] {
+        &self.colours
+    }
+
+    /// Get the colour at `index`.
+    #[must_use]
+    pub const fn colour(&self, index: u8) -> Colour {
+        self.colours[index as usize]
+    }
+
+    /// Set the colour at `index`.
+    pub fn set_colour(&mut self, index: u8, colour: Colour) {
+        self.colours[index as usize] = colour;
+        self.nearest.clear();
+    }
+
+    /// Move each colour in `range` `steps` places towards its end, with those that fall off the
+    /// end coming round to the start, or the other way for negative `steps`. Rotating a little
+    /// each frame makes water, lava and the like appear to flow.
+    pub fn rotate(&mut self, range: RangeInclusive<u8>, steps: i32) {
+        let (start, end) = (usize::from(*range.start()), usize::from(*range.end()));
+        let Some(colours) = self.colours.get_mut(start..=end) else {
+            return;
+        };
+        let Ok(length @ 1..) = i32::try_from(colours.len()) else {
+            return;
+        };
+        #[allow(clippy::cast_sign_loss)]
+        colours.rotate_right(steps.rem_euclid(length) as usize);
+        self.nearest.clear();
+    }
+
+    /// Get the index of the colour closest to `colour`, ignoring alpha. The lowest index wins
+    /// among colours equally close.
+    #[must_use]
+    pub fn nearest(&self, colour: Colour) -> u8 {
+        let rgb = u32::from(colour);
+        if let Some(index) = self.nearest.get(rgb) {
+            return index;
+        }
+        let index = self.search(colour);
+        self.nearest.insert(rgb, index);
+        index
+    }
+
+    /// Find the index of the colour closest to `colour` by comparing it with every entry.
+    fn search(&self, colour: Colour) -> u8 {
+        let distance = |entry: &Colour| {
+            [
+                (entry.red, colour.red),
+                (entry.green, colour.green),
+                (entry.blue, colour.blue),
+            ]
+            .map(|(a, b)| u32::from(a.abs_diff(b)).pow(2))
+            .iter()
+            .sum::<u32>()
+        };
+        let mut nearest = (0, u32::MAX);
+        for (index, entry) in (0..=u8::MAX).zip(&self.colours) {
+            let distance = distance(entry);
+            if distance < nearest.1 {
+                nearest = (index, distance);
+                if distance == 0 {
+                    break;
+                }
+            }
+        }
+        nearest.0
+    }
+
+    /// Convert `regions` of `canvas`, which picks from this palette, into the pixel format of
+    /// the canvas in `converted`, resizing it to hold the whole canvas without padding.
+    pub(crate) fn convert_into(
+        &self,
+        canvas: &Canvas,
+        regions: &[Region],
+        converted: &mut Vec<u8>,
+    ) {
+        let bytes_per_pixel = canvas.pixel_format.bytes_per_pixel();
+        let stride = canvas.width as usize * bytes_per_pixel;
+        converted.resize(stride * canvas.height as usize, 0);
+        let colours = self.colours.map(|colour| canvas.pixel_format.pack(colour));
+        for region in regions {
+            let (x, width) = (region.x as usize, region.width as usize);
+            for y in region.y as usize..region.bottom() as usize {
+                let indices = &canvas.buffer[y * canvas.stride + x..][..width];
+                let row =
+                    &mut converted[y * stride + x * bytes_per_pixel..][..width * bytes_per_pixel];
+                for (pixel, &index) in row.chunks_exact_mut(bytes_per_pixel).zip(indices) {
+                    pixel.copy_from_slice(&colours[usize::from(index)][..bytes_per_pixel]);
+                }
+            }
+        }
+    }
+}
+
+/// Answers already found by [`Palette::nearest`], each packed with the colour looked up in its
+/// red, green and blue bits and the index in its lowest byte, and a bit above them marking the
+/// slot used. A colour only ever goes in one slot, replacing whatever was there.
+#[derive(Clone)]
+struct NearestCache(Box<[Cell<u64>]>);
+
+impl NearestCache {
+    const USED: u64 = 1 << 32;
+
+    fn new() -> Self {
+        Self(vec![Cell::new(0); NEAREST_CACHE_SIZE].into_boxed_slice())
+    }
+
+    /// Get the slot for the colour `rgb`, as `0xRRGGBB`.
+    fn slot(&self, rgb: u32) -> &Cell<u64> {
+        // Fibonacci hashing, keeping the top bits, which depend on every bit of the colour
+        let hash = rgb.wrapping_mul(0x9e37_79b9) >> (32 - NEAREST_CACHE_SIZE.trailing_zeros());
+        &self.0[hash as usize]
+    }
+
+    /// Get the entry for the colour `rgb` without its index.
+    fn key(rgb: u32) -> u64 {
+        Self::USED | u64::from(rgb) << 8
+    }
+
+    fn get(&self, rgb: u32) -> Option<u8> {
+        let entry = self.slot(rgb).get();
+        #[allow(clippy::cast_possible_truncation)]
+        (entry & !0xff == Self::key(rgb)).then_some(entry as u8)
+    }
+
+    fn insert(&self, rgb: u32, index: u8) {
+        self.slot(rgb).set(Self::key(rgb) | u64::from(index));
+    }
+
+    fn clear(&mut self) {
+        self.0.fill(Cell::new(0));
+    }
+}
+
+/// How the pixels of a canvas are stored: packed in a pixel format, or as indices into a
+/// palette.
+#[derive(Clone, Copy)]
+pub(crate) struct PixelEncoding<'a> {
+    pixel_format: PixelFormat,
+    palette: Option<&'a Palette>,
+}
+
+impl<'a> PixelEncoding<'a> {
+    pub(crate) const fn new(pixel_format: PixelFormat, palette: Option<&'a Palette>) -> Self {
+        Self {
+            pixel_format,
+            palette,
+        }
+    }
+
+    /// Number of bytes used to store a single pixel.
+    pub(crate) const fn bytes_per_pixel(self) -> usize {
+        if self.palette.is_some() {
+            1
+        } else {
+            self.pixel_format.bytes_per_pixel()
+        }
+    }
+
+    /// Store a colour in the bytes of a single pixel, as the nearest colour in the palette if
+    /// there is one. Only the first [`bytes_per_pixel`](Self::bytes_per_pixel) bytes are
+    /// meaningful.
+    pub(crate) fn pack(self, colour: Colour) -> [u8; 4] {
+        self.palette.map_or_else(
+            || self.pixel_format.pack(colour),
+            |palette| [palette.nearest(colour), 0, 0, 0],
+        )
+    }
+
+    /// Get the colour of a single pixel from its bytes.
+    pub(crate) fn unpack(self, bytes: &[u8]) -> Colour {
+        self.palette.map_or_else(
+            || self.pixel_format.unpack(bytes),
+            |palette| palette.colour(bytes[0]).with_alpha(u8::MAX),
+        )
+    }
+}
+
+/// Draw the screen in indexed colour, with each pixel one byte picking a colour from `palette`,
+/// converted to the pixel format of the display only when frames are shown.
+///
+/// Colours drawn are replaced by the nearest colour in the palette, so give entries different
+/// colours to be able to draw with each of them. The first call clears the screen to the first
+/// colour in the palette; later calls only swap the palette, changing the colours of everything
+/// on the screen.
+pub fn set_palette(palette: &Palette) {
+    get().frame_buffer.canvas.set_palette(palette);
+}
+
+/// Draw the screen in the colours of the display again after [`set_palette`].
+///
+/// The contents of the screen are cleared.
+pub fn reset_palette() {
+    get().frame_buffer.canvas.reset_palette();
+}
+
+/// Get a copy of the palette of the screen, if it is drawn in indexed colour.
+#[must_use]
+pub fn get_palette() -> Option<Palette> {
+    get().frame_buffer.canvas.palette().cloned()
+}
+
+/// Rotate colours in the palette of the screen, changing the colours of everything on it. See
+/// [`Palette::rotate`].
+pub fn rotate_palette(range: RangeInclusive<u8>, steps: i32) {
+    get().frame_buffer.canvas.rotate_palette(range, steps);
+}
+
+impl Canvas {
+    /// Create an indexed canvas of `width` by `height` pixels using `palette`, filled with its
+    /// first colour. See [`set_palette`](Self::set_palette).
+    #[must_use]
+    pub fn with_palette(width: u32, height: u32, palette: &Palette) -> Self {
+        let mut canvas = Self::new(width, height);
+        canvas.set_palette(palette);
+        canvas
+    }
+
+    /// Get the palette the pixels of the canvas pick from, if it is indexed.
+    #[must_use]
+    pub fn palette(&self) -> Option<&Palette> {
+        self.palette.as_deref()
+    }
+
+    /// Store each pixel of the canvas as one byte picking a colour from `palette`, using a
+    /// quarter of the memory of 32 bits per pixel.
+    ///
+    /// Colours drawn are replaced by the nearest colour in the palette. If the canvas is not
+    /// already indexed it is cleared to the first colour in the palette; otherwise only the
+    /// palette is swapped, changing the colours of everything on the canvas.
+    pub fn set_palette(&mut self, palette: &Palette) {
+        if self.palette.is_none() {
+            self.stride = self.width as usize;
+            self.buffer = vec![0; self.stride * self.height as usize];
+        }
+        self.palette = Some(Box::new(palette.clone()));
+        self.mark_all_dirty();
+    }
+
+    /// Store pixels in the pixel format of the canvas again after
+    /// [`set_palette`](Self::set_palette).
+    ///
+    /// The contents of the canvas are cleared to black.
+    pub fn reset_palette(&mut self) {
+        if self.palette.take().is_none() {
+            return;
+        }
+        self.stride = self.width as usize * self.pixel_format.bytes_per_pixel();
+        self.buffer = vec![0; self.stride * self.height as usize];
+        self.mark_all_dirty();
+    }
+
+    /// Rotate colours in the palette of the canvas, if it is indexed. See [`Palette::rotate`].
+    pub fn rotate_palette(&mut self, range: RangeInclusive<u8>, steps: i32) {
+        if let Some(palette) = &mut self.palette {
+            palette.rotate(range, steps);
+            self.mark_all_dirty();
+        }
+    }
+
+    /// Get how the pixels of the canvas are stored.
+    pub(crate) fn encoding(&self) -> PixelEncoding<'_> {
+        PixelEncoding::new(self.pixel_format, self.palette.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::colour::{colour, BlendMode, BLUE, GREEN, RED, WHITE};
+    use super::super::testing::screen;
+    use super::super::{draw_rectangle, get_pixel, next_frame, set_blend_mode};
+    use super::*;
+
+    /// Colours of the first `count` pixels of a frame shown on the headless display.
+    fn shown(frame: &[u8], count: usize) -> Vec<Colour> {
+        frame[..count * 4]
+            .chunks_exact(4)
+            .map(|pixel| colour(pixel[2], pixel[1], pixel[0]))
+            .collect()
+    }
+
+    #[test]
+    fn rotate_moves_colours_round_within_the_range() {
+        let mut palette = Palette::new(&[BLACK, RED, GREEN, BLUE, WHITE]);
+        palette.rotate(1..=3, 1);
+        assert_eq!(palette.colours()[..5], [BLACK, BLUE, RED, GREEN, WHITE]);
+        palette.rotate(1..=3, -4);
+        assert_eq!(palette.colours()[..5], [BLACK, RED, GREEN, BLUE, WHITE]);
+    }
+
+    #[test]
+    fn nearest_picks_the_closest_colour() {
+        let palette = Palette::new(&[BLACK, RED, WHITE]);
+        assert_eq!(palette.nearest(colour(200, 30, 20)), 1);
+        assert_eq!(palette.nearest(colour(200, 200, 200)), 2);
+        // every entry past the first three is black too
+        assert_eq!(palette.nearest(colour(10, 0, 0)), 0);
+    }
+
+    #[test]
+    fn nearest_follows_changes_to_the_palette() {
+        let mut palette = Palette::new(&[BLACK, RED, WHITE]);
+        let pink = colour(255, 64, 64);
+        assert_eq!(palette.nearest(pink), 1);
+        palette.set_colour(3, pink);
+        assert_eq!(palette.nearest(pink), 3);
+        palette.rotate(0..=3, 1);
+        assert_eq!(palette.nearest(pink), 0);
+        assert_eq!(palette.nearest(RED), 2);
+    }
+
+    #[test]
+    fn blending_on_an_indexed_screen_picks_the_nearest_entry() {
+        let _screen = screen();
+        let dark_red = colour(128, 0, 0);
+        set_palette(&Palette::new(&[BLACK, RED, dark_red, WHITE]));
+        draw_rectangle(0, 0, 2, 1, RED.with_alpha(128));
+        assert_eq!(get_pixel(0, 0), Some(dark_red));
+        set_blend_mode(BlendMode::Additive);
+        draw_rectangle(0, 0, 1, 1, colour(120, 0, 0));
+        set_blend_mode(BlendMode::Multiply);
+        draw_rectangle(1, 0, 1, 1, colour(128, 128, 128));
+        assert_eq!(get_pixel(0, 0), Some(RED));
+        assert_eq!(get_pixel(1, 0), Some(BLACK));
+    }
+
+    #[test]
+    fn the_screen_is_shown_in_the_colours_of_its_palette() {
+        let (_guard, display) = screen();
+        set_palette(&Palette::new(&[BLACK, RED, BLUE]));
+        draw_rectangle(1, 0, 1, 1, colour(250, 10, 0));
+        draw_rectangle(2, 0, 1, 1, colour(0, 20, 200));
+        assert_eq!(get_pixel(1, 0), Some(RED));
+        next_frame().expect("the headless display cannot fail");
+        assert_eq!(shown(&display.frame(), 3), [BLACK, RED, BLUE]);
+        rotate_palette(1..=2, 1);
+        next_frame().expect("the headless display cannot fail");
+        assert_eq!(shown(&display.frame(), 3), [BLACK, BLUE, RED]);
+    }
+
+    #[test]
+    fn set_palette_after_reset_palette_shows_the_palette_again() {
+        let (_guard, display) = screen();
+        set_palette(&Palette::new(&[GREEN]));
+        next_frame().expect("the headless display cannot fail");
+        reset_palette();
+        draw_rectangle(0, 0, 1, 1, WHITE);
+        next_frame().expect("the headless display cannot fail");
+        assert_eq!(shown(&display.frame(), 2), [WHITE, BLACK]);
+        assert_eq!(get_palette(), None);
+        set_palette(&Palette::new(&[BLUE, RED]));
+        next_frame().expect("the headless display cannot fail");
+        assert_eq!(shown(&display.frame(), 2), [BLUE, BLUE]);
+    }
+}
@@ -0,0 +1,454 @@
+use std::{
+    fs::{remove_file, File, OpenOptions},
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use flate2::Crc;
+use gif::{Encoder, Frame, Repeat};
+use log::info;
+use png::{BitDepth, ColorType};
+
+use crate::{
+    context::{get, Context},
+    error::{Error, Result},
+    input::{is_active, Input},
+};
+
+use super::{canvas::Canvas, colour::Colour};
+
+/// File format of a recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecordingFormat {
+    /// Animated GIF.
+    #[default]
+    Gif,
+    /// Animated PNG.
+    Apng,
+}
+
+impl RecordingFormat {
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Gif => "gif",
+            Self::Apng => "png",
+        }
+    }
+}
+
+/// Options for [`start_recording`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordingSettings {
+    /// File format to write.
+    pub format: RecordingFormat,
+    /// Keep one in every `downscale` pixels in each direction; 1 records at full size.
+    pub downscale: u32,
+    /// Record one in every `frame_step` frames; 1 records every frame.
+    pub frame_step: u32,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            format: RecordingFormat::default(),
+            downscale: 1,
+            frame_step: 1,
+        }
+    }
+}
+
+/// Start recording every frame shown by [`next_frame`](super::next_frame) to an animated image
+/// at `path`, stopping any recording already in progress.
+///
+/// Frames are reduced to a fixed palette of 252 colours and written as they are captured, so
+/// memory use does not grow with the length of the recording.
+///
+/// # Errors
+///
+/// If the file cannot be created or written, or a previous recording cannot be finished, an
+/// error is returned, as it is if the screen is too large for the format, after any
+/// `downscale`.
+pub fn start_recording<P: AsRef<Path>>(path: P, settings: RecordingSettings) -> Result<()> {
+    let context = get();
+    stop_recording()?;
+    context.recorder = Some(Recorder::new(
+        path.as_ref(),
+        settings,
+        &context.frame_buffer.canvas,
+    )?);
+    Ok(())
+}
+
+/// Stop recording and finish the file. Does nothing if no recording is in progress.
+///
+/// An animated PNG is removed rather than finished if no frames were shown, since it needs at
+/// least one.
+///
+/// # Errors
+///
+/// If the file cannot be written, an error is returned.
+pub fn stop_recording() -> Result<()> {
+    get().recorder.take().map_or(Ok(()), Recorder::finish)
+}
+
+/// Return true if a recording is in progress.
+#[must_use]
+pub fn is_recording() -> bool {
+    get().recorder.is_some()
+}
+
+/// Start and stop recording when `input` is pressed, or stop listening for a hotkey if `None`.
+///
+/// Recordings are saved in the working directory, named after the time they were started.
+pub fn set_recording_hotkey(input: Option<Input>, settings: RecordingSettings) {
+    get().recording_hotkey = input.map(|input| RecordingHotkey {
+        input,
+        settings,
+        was_active: false,
+    });
+}
+
+pub(crate) struct RecordingHotkey {
+    input: Input,
+    settings: RecordingSettings,
+    was_active: bool,
+}
+
+/// Handle the recording hotkey and capture the frame about to be shown.
+pub(crate) fn update(context: &mut Context) -> Result<()> {
+    if let Some(hotkey) = &mut context.recording_hotkey {
+        let active = is_active(hotkey.input)?;
+        let pressed = active && !hotkey.was_active;
+        hotkey.was_active = active;
+        if pressed {
+            if let Some(recorder) = context.recorder.take() {
+                recorder.finish()?;
+            } else {
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                let path = format!("recording-{time}.{}", hotkey.settings.format.extension());
+                context.recorder = Some(Recorder::new(
+                    Path::new(&path),
+                    hotkey.settings,
+                    &context.frame_buffer.canvas,
+                )?);
+            }
+        }
+    }
+    if let Some(recorder) = &mut context.recorder {
+        recorder.capture(&context.frame_buffer.canvas)?;
+    }
+    Ok(())
+}
+
+enum Writer {
+    /// The encoder, and the size of the recording, which the format limits to 16 bits.
+    Gif(Encoder<BufWriter<File>>, (u16, u16)),
+    Apng(ApngWriter),
+}
+
+pub(crate) struct Recorder {
+    writer: Writer,
+    settings: RecordingSettings,
+    width: u32,
+    height: u32,
+    frames_seen: u32,
+    /// Last captured frame, written once the next one shows how long it was on screen.
+    pending: Option<(Vec<u8>, Instant)>,
+    last_delay: Duration,
+}
+
+impl Recorder {
+    fn new(path: &Path, settings: RecordingSettings, canvas: &Canvas) -> Result<Self> {
+        info!("recording to {}", path.display());
+        let settings = RecordingSettings {
+            downscale: settings.downscale.max(1),
+            frame_step: settings.frame_step.max(1),
+            ..settings
+        };
+        let (width, height) = (canvas.width, canvas.height);
+        let (width, height) = (
+            width.div_ceil(settings.downscale),
+            height.div_ceil(settings.downscale),
+        );
+        let writer = match settings.format {
+            RecordingFormat::Gif => {
+                let (Ok(gif_width), Ok(gif_height)) = (u16::try_from(width), u16::try_from(height))
+                else {
+                    return Err(Error::RecordingTooLarge);
+                };
+                let file = BufWriter::new(File::create(path)?);
+                let mut encoder = Encoder::new(file, gif_width, gif_height, &palette())?;
+                encoder.set_repeat(Repeat::Infinite)?;
+                Writer::Gif(encoder, (gif_width, gif_height))
+            }
+            RecordingFormat::Apng => Writer::Apng(ApngWriter::new(path, width, height)?),
+        };
+        Ok(Self {
+            writer,
+            settings,
+            width,
+            height,
+            frames_seen: 0,
+            pending: None,
+            last_delay: Duration::from_millis(33),
+        })
+    }
+
+    fn capture(&mut self, canvas: &Canvas) -> Result<()> {
+        let skip = !self.frames_seen.is_multiple_of(self.settings.frame_step);
+        self.frames_seen = self.frames_seen.wrapping_add(1);
+        if skip {
+            return Ok(());
+        }
+        let downscale = self.settings.downscale as usize;
+        let encoding = canvas.encoding();
+        let bytes_per_pixel = encoding.bytes_per_pixel();
+        let mut indices = Vec::with_capacity(self.width as usize * self.height as usize);
+        for y in (0..canvas.height as usize).step_by(downscale) {
+            let row = &canvas.buffer[y * canvas.stride..];
+            for x in (0..canvas.width as usize).step_by(downscale) {
+                let pixel = &row[x * bytes_per_pixel..(x + 1) * bytes_per_pixel];
+                indices.push(palette_index(encoding.unpack(pixel)));
+            }
+        }
+        let now = Instant::now();
+        if let Some((previous, time)) = self.pending.replace((indices, now)) {
+            self.last_delay = now - time;
+            self.write(&previous, self.last_delay)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, indices: &[u8], delay: Duration) -> Result<()> {
+        match &mut self.writer {
+            Writer::Gif(encoder, (width, height)) => {
+                #[allow(clippy::cast_possible_truncation)]
+                let frame = Frame {
+                    width: *width,
+                    height: *height,
+                    delay: (delay.as_millis() / 10).clamp(1, u128::from(u16::MAX)) as u16,
+                    buffer: indices.into(),
+                    ..Frame::default()
+                };
+                encoder.write_frame(&frame)?;
+            }
+            Writer::Apng(writer) => writer.write_frame(indices, delay)?,
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if let Some((indices, _)) = self.pending.take() {
+            self.write(&indices, self.last_delay)?;
+        }
+        match self.writer {
+            Writer::Gif(encoder, _) => encoder.into_inner()?.flush()?,
+            Writer::Apng(writer) => writer.finish()?,
+        }
+        info!("recording finished");
+        Ok(())
+    }
+}
+
+const RED_LEVELS: u8 = 6;
+const GREEN_LEVELS: u8 = 7;
+const BLUE_LEVELS: u8 = 6;
+
+/// Fixed palette of evenly spaced colours, as RGB triples.
+fn palette() -> Vec<u8> {
+    let level = |value: u8, levels: u8| {
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            (u16::from(value) * 255 / u16::from(levels - 1)) as u8
+        }
+    };
+    let mut palette = Vec::new();
+    for red in 0..RED_LEVELS {
+        for green in 0..GREEN_LEVELS {
+            for blue in 0..BLUE_LEVELS {
+                palette.extend([
+                    level(red, RED_LEVELS),
+                    level(green, GREEN_LEVELS),
+                    level(blue, BLUE_LEVELS),
+                ]);
+            }
+        }
+    }
+    palette
+}
+
+/// Index of the nearest colour in [`palette`].
+fn palette_index(colour: Colour) -> u8 {
+    let level = |value: u8, levels: u8| {
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            ((u16::from(value) * u16::from(levels - 1) + 127) / 255) as u8
+        }
+    };
+    (level(colour.red, RED_LEVELS) * GREEN_LEVELS + level(colour.green, GREEN_LEVELS)) * BLUE_LEVELS
+        + level(colour.blue, BLUE_LEVELS)
+}
+
+/// Streaming APNG writer for palette images.
+///
+/// The encoder needs the number of frames before writing the first, so it is told there are as
+/// many as can be and the real count is patched into the animation control chunk when the file
+/// is finished.
+struct ApngWriter {
+    writer: png::Writer<BufWriter<File>>,
+    /// Second handle to the file, for patching it once the encoder is done with it.
+    file: File,
+    path: PathBuf,
+    frames: u32,
+}
+
+impl ApngWriter {
+    fn new(path: &Path, width: u32, height: u32) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file.try_clone()?), width, height);
+        encoder.set_color(ColorType::Indexed);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_palette(palette());
+        encoder.set_compression(png::Compression::Fast);
+        encoder.set_animated(u32::MAX, 0)?;
+        Ok(Self {
+            writer: encoder.write_header()?,
+            file,
+            path: path.to_path_buf(),
+            frames: 0,
+        })
+    }
+
+    fn write_frame(&mut self, indices: &[u8], delay: Duration) -> Result<()> {
+        #[allow(clippy::cast_possible_truncation)]
+        let delay = delay.as_millis().min(u128::from(u16::MAX)) as u16;
+        self.writer.set_frame_delay(delay, 1000)?;
+        self.writer.write_image_data(indices)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Finish the file, or remove it if no frames were written, as an animation needs at least
+    /// one.
+    fn finish(mut self) -> Result<()> {
+        if self.frames == 0 {
+            drop(self.writer);
+            remove_file(&self.path)?;
+            return Ok(());
+        }
+        self.writer.finish()?;
+        set_frame_count(&mut self.file, self.frames)
+    }
+}
+
+/// Set the number of frames in the `acTL` chunk of a finished APNG file.
+fn set_frame_count(file: &mut File, frames: u32) -> Result<()> {
+    // skip the signature
+    let mut position = 8;
+    loop {
+        let mut header = [0; 8];
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut header)?;
+        let [a, b, c, d, kind @ ..] = header;
+        if &kind == b"acTL" {
+            let mut data = [0; 8];
+            file.read_exact(&mut data)?;
+            data[..4].copy_from_slice(&frames.to_be_bytes());
+            let mut crc = Crc::new();
+            crc.update(&kind);
+            crc.update(&data);
+            file.seek(SeekFrom::Start(position + 8))?;
+            file.write_all(&data)?;
+            file.write_all(&crc.sum().to_be_bytes())?;
+            return Ok(());
+        }
+        // length, kind, data and checksum
+        position += 12 + u64::from(u32::from_be_bytes([a, b, c, d]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::process::id;
+
+    use png::Decoder;
+
+    use super::super::colour::RED;
+    use super::super::testing::screen;
+    use super::super::{draw_rectangle, next_frame};
+    use super::*;
+
+    fn apng() -> RecordingSettings {
+        RecordingSettings {
+            format: RecordingFormat::Apng,
+            ..RecordingSettings::default()
+        }
+    }
+
+    #[test]
+    fn apng_recordings_hold_every_frame() {
+        let _screen = screen();
+        let path = temp_dir().join(format!("pigame-{}-frames.png", id()));
+        start_recording(&path, apng()).expect("the file can be created");
+        for x in 0..3 {
+            draw_rectangle(x, 0, 1, 1, RED);
+            next_frame().expect("the headless display cannot fail");
+        }
+        stop_recording().expect("the file can be written");
+        let decoder = Decoder::new(File::open(&path).expect("the recording was written"));
+        let mut reader = decoder.read_info().expect("the recording is a png");
+        let frames = reader
+            .info()
+            .animation_control()
+            .map(|control| control.num_frames);
+        assert_eq!(frames, Some(3));
+        let mut frame = vec![0; reader.output_buffer_size()];
+        for _ in 0..3 {
+            reader
+                .next_frame(&mut frame)
+                .expect("every frame can be read");
+        }
+        remove_file(path).expect("the recording can be removed");
+    }
+
+    #[test]
+    fn gif_recordings_too_wide_for_the_format_are_refused() {
+        let _screen = screen();
+        let path = temp_dir().join(format!("pigame-{}-wide.gif", id()));
+        let canvas = Canvas::new(u32::from(u16::MAX) + 1, 1);
+        let settings = RecordingSettings::default();
+        assert!(matches!(
+            Recorder::new(&path, settings, &canvas),
+            Err(Error::RecordingTooLarge)
+        ));
+        assert!(!path.exists());
+        let halved = RecordingSettings {
+            downscale: 2,
+            ..settings
+        };
+        Recorder::new(&path, halved, &canvas)
+            .expect("the recording fits once downscaled")
+            .finish()
+            .expect("the file can be written");
+        remove_file(path).expect("the recording can be removed");
+    }
+
+    #[test]
+    fn empty_apng_recordings_are_removed() {
+        let _screen = screen();
+        let path = temp_dir().join(format!("pigame-{}-empty.png", id()));
+        start_recording(&path, apng()).expect("the file can be created");
+        stop_recording().expect("the file can be removed");
+        assert!(!path.exists());
+    }
+}
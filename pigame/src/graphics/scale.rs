@@ -0,0 +1,98 @@
+use std::ops::Range;
+
+use super::backend::Region;
+
+/// Largest whole factor a `source` sized frame can be scaled up by to fit a `destination` sized
+/// one (at least 1), and the offset of the scaled frame within the destination when centred.
+fn placement(
+    (source_width, source_height): (u32, u32),
+    (width, height): (u32, u32),
+) -> (u32, i64, i64) {
+    let factor = (width / source_width.max(1))
+        .min(height / source_height.max(1))
+        .max(1);
+    let offset_x = (i64::from(width) - i64::from(source_width * factor)) / 2;
+    let offset_y = (i64::from(height) - i64::from(source_height * factor)) / 2;
+    (factor, offset_x, offset_y)
+}
+
+/// Where `region` of a `source` sized frame ends up after [`scale_into`] a `destination` sized
+/// one.
+pub(super) fn scale_region(
+    region: Region,
+    source: (u32, u32),
+    (width, height): (u32, u32),
+) -> Region {
+    let (factor, offset_x, offset_y) = placement(source, (width, height));
+    let scale = |start: u32, end: u32, offset: i64, limit: u32| {
+        let clamp = |value: i64| {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            {
+                value.clamp(0, i64::from(limit)) as u32
+            }
+        };
+        let start = clamp(i64::from(start * factor) + offset);
+        (start, clamp(i64::from(end * factor) + offset) - start)
+    };
+    let (x, region_width) = scale(region.x, region.right(), offset_x, width);
+    let (y, region_height) = scale(region.y, region.bottom(), offset_y, height);
+    Region::new(x, y, region_width, region_height)
+}
+
+/// Nearest-neighbour scale `source` into the centre of `destination` by the largest whole factor
+/// that fits, or 1 if the source is larger than the destination, in which case it is cropped.
+///
+/// Sizes are `(width, height, stride)`. Only destination `rows` are written, and pixels of
+/// `destination` outside the scaled image are left untouched.
+pub(super) fn scale_into(
+    source: &[u8],
+    (source_width, source_height, source_stride): (u32, u32, usize),
+    destination: &mut [u8],
+    (width, height, stride): (u32, u32, usize),
+    bytes_per_pixel: usize,
+    rows: Range<usize>,
+) {
+    if source_width == 0 || source_height == 0 {
+        return;
+    }
+    let (factor, offset_x, offset_y) = placement((source_width, source_height), (width, height));
+    let (scaled_width, scaled_height) = (source_width * factor, source_height * factor);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let (first_x, last_x) = (
+        offset_x.max(0) as usize,
+        (offset_x + i64::from(scaled_width)).min(i64::from(width)) as usize,
+    );
+    let factor = factor as usize;
+    let mut previous_source_y = None;
+    for y in rows.start..rows.end.min(height as usize) {
+        #[allow(clippy::cast_possible_wrap)]
+        let scaled_y = y as i64 - offset_y;
+        if scaled_y < 0 || scaled_y >= i64::from(scaled_height) {
+            continue;
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let source_y = scaled_y as usize / factor;
+        let row = y * stride;
+        if previous_source_y == Some(source_y) {
+            // repeated row; copy the one just written instead of scaling it again
+            let range =
+                row - stride + first_x * bytes_per_pixel..row - stride + last_x * bytes_per_pixel;
+            destination.copy_within(range, row + first_x * bytes_per_pixel);
+            continue;
+        }
+        previous_source_y = Some(source_y);
+        let source_row = &source[source_y * source_stride..];
+        for x in first_x..last_x {
+            #[allow(
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss,
+                clippy::cast_possible_wrap
+            )]
+            let source_x = (x as i64 - offset_x) as usize / factor;
+            let source_start = source_x * bytes_per_pixel;
+            let start = row + x * bytes_per_pixel;
+            destination[start..start + bytes_per_pixel]
+                .copy_from_slice(&source_row[source_start..source_start + bytes_per_pixel]);
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use png::{BitDepth, ColorType, Encoder};
+
+use crate::{context::get, error::Result};
+
+/// Get the frame being drawn as RGBA bytes, 4 per pixel, row by row from the top left.
+///
+/// The frame is [`screen_width`](super::screen_width) by [`screen_height`](super::screen_height)
+/// pixels, whatever the pixel format of the display.
+#[must_use]
+pub fn screenshot_rgba() -> Vec<u8> {
+    get().frame_buffer.canvas.to_rgba()
+}
+
+/// Save the frame being drawn as a PNG file.
+///
+/// # Errors
+///
+/// If the file cannot be created or written, an error is returned.
+pub fn screenshot<P: AsRef<Path>>(path: P) -> Result<()> {
+    let (width, height) = get().frame_buffer.screen_size();
+    let mut encoder = Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&screenshot_rgba())?;
+    writer.finish()?;
+    Ok(())
+}
@@ -0,0 +1,637 @@
+use glam::IVec2;
+
+use crate::context::get;
+
+use super::canvas::Canvas;
+use super::colour::Colour;
+
+/// Draw a line `thickness` pixels wide on the screen from `(x1, y1)` to `(x2, y2)`, both ends
+/// included.
+pub fn draw_line(x1: i32, y1: i32, x2: i32, y2: i32, thickness: u32, colour: Colour) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_line(x1, y1, x2, y2, thickness, colour);
+}
+
+/// Draw a filled circle on the screen centred on `(x, y)`.
+pub fn draw_circle(x: i32, y: i32, radius: u32, colour: Colour) {
+    get().frame_buffer.canvas.draw_circle(x, y, radius, colour);
+}
+
+/// Draw the outline of a circle on the screen centred on `(x, y)`, `thickness` pixels wide.
+pub fn draw_circle_lines(x: i32, y: i32, radius: u32, thickness: u32, colour: Colour) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_circle_lines(x, y, radius, thickness, colour);
+}
+
+/// Draw a filled ellipse on the screen centred on `(x, y)`.
+pub fn draw_ellipse(x: i32, y: i32, radius_x: u32, radius_y: u32, colour: Colour) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_ellipse(x, y, radius_x, radius_y, colour);
+}
+
+/// Draw the outline of an ellipse on the screen centred on `(x, y)`, `thickness` pixels wide.
+pub fn draw_ellipse_lines(
+    x: i32,
+    y: i32,
+    radius_x: u32,
+    radius_y: u32,
+    thickness: u32,
+    colour: Colour,
+) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_ellipse_lines(x, y, radius_x, radius_y, thickness, colour);
+}
+
+/// Draw a filled rectangle on the screen with its top left corner at `(x, y)` and corners rounded
+/// to `radius`.
+pub fn draw_rounded_rectangle(x: i32, y: i32, w: i32, h: i32, radius: u32, colour: Colour) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_rounded_rectangle(x, y, w, h, radius, colour);
+}
+
+/// Draw the outline of a rectangle on the screen with its top left corner at `(x, y)` and corners
+/// rounded to `radius`, `thickness` pixels wide inside its edges.
+pub fn draw_rounded_rectangle_lines(
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+    radius: u32,
+    thickness: u32,
+    colour: Colour,
+) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_rounded_rectangle_lines(x, y, w, h, radius, thickness, colour);
+}
+
+/// Draw a filled triangle on the screen.
+pub fn draw_triangle(a: IVec2, b: IVec2, c: IVec2, colour: Colour) {
+    get().frame_buffer.canvas.draw_triangle(a, b, c, colour);
+}
+
+/// Draw the outline of a triangle on the screen, `thickness` pixels wide.
+pub fn draw_triangle_lines(a: IVec2, b: IVec2, c: IVec2, thickness: u32, colour: Colour) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_triangle_lines(a, b, c, thickness, colour);
+}
+
+/// Draw a filled polygon on the screen. See [`Canvas::draw_polygon`].
+pub fn draw_polygon(points: &[IVec2], colour: Colour) {
+    get().frame_buffer.canvas.draw_polygon(points, colour);
+}
+
+/// Draw the outline of a polygon on the screen, `thickness` pixels wide.
+pub fn draw_polygon_lines(points: &[IVec2], thickness: u32, colour: Colour) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_polygon_lines(points, thickness, colour);
+}
+
+impl Canvas {
+    /// Draw a line `thickness` pixels wide from `(x1, y1)` to `(x2, y2)`, both ends included.
+    pub fn draw_line(
+        &mut self,
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+        thickness: u32,
+        colour: Colour,
+    ) {
+        if thickness == 0 {
+            return;
+        }
+        if let Some(camera) = self.turned_camera() {
+            let (start, end) = (
+                camera.pixel_to_screen(x1, y1),
+                camera.pixel_to_screen(x2, y2),
+            );
+            let thickness = thickness.saturating_mul(camera.zoom());
+            self.without_camera(|canvas| {
+                canvas.draw_line(start.x, start.y, end.x, end.y, thickness, colour);
+            });
+            return;
+        }
+        let thickness = i32::try_from(thickness).unwrap_or(i32::MAX);
+        let before = (thickness - 1) / 2;
+        let after = thickness - 1 - before;
+        let (left, right) = (
+            x1.min(x2).saturating_sub(before),
+            x1.max(x2).saturating_add(after),
+        );
+        let (top, bottom) = (
+            y1.min(y2).saturating_sub(before),
+            y1.max(y2).saturating_add(after),
+        );
+        if !self.overlaps(left, top, right, bottom) {
+            return;
+        }
+        self.mark_dirty_world(left, top, right, bottom);
+
+        let (first_column, first_row, last_column, last_row) = self.visible_bounds();
+        let steep = y1.abs_diff(y2) > x1.abs_diff(x2);
+        // the axis the line mostly runs along, which it steps along one pixel at a time, and the
+        // axis across it
+        let ((start, end, first, last), (across_start, across_end)) = if steep {
+            ((y1, y2, first_row, last_row), (x1, x2))
+        } else {
+            ((x1, x2, first_column, last_column), (y1, y2))
+        };
+        let [start, end, first, last, across_start, across_end] =
+            [start, end, first, last, across_start, across_end].map(i64::from);
+        let (length, across_length) = ((end - start).abs(), (across_end - across_start).abs());
+        let (direction, across_direction) =
+            ((end - start).signum(), (across_end - across_start).signum());
+        // only the steps that land on the canvas
+        let (low, high) = if direction < 0 {
+            (start - last, start - first)
+        } else {
+            (first - start, last - start)
+        };
+        for along in low.max(0)..=high.min(length) {
+            // the pixel across the line Bresenham's algorithm picks, rounding halves away from
+            // the start
+            let across = if length == 0 {
+                0
+            } else {
+                (2 * i128::from(across_length) * i128::from(along) + i128::from(length))
+                    / (2 * i128::from(length))
+            };
+            // both lie between the ends of the line, so fit
+            #[allow(clippy::cast_possible_truncation)]
+            let (along, across) = (
+                (start + direction * along) as i32,
+                (i128::from(across_start) + i128::from(across_direction) * across) as i32,
+            );
+            // widen across the direction the line mostly runs in
+            if steep {
+                self.fill_world_span(
+                    along,
+                    across.saturating_sub(before),
+                    across.saturating_add(after),
+                    colour,
+                );
+            } else {
+                let rows = across.saturating_sub(before).max(first_row)
+                    ..=across.saturating_add(after).min(last_row);
+                for row in rows {
+                    self.fill_world_span(row, along, along, colour);
+                }
+            }
+        }
+    }
+
+    /// Draw a filled circle centred on `(x, y)`.
+    pub fn draw_circle(&mut self, x: i32, y: i32, radius: u32, colour: Colour) {
+        self.draw_ellipse(x, y, radius, radius, colour);
+    }
+
+    /// Draw the outline of a circle centred on `(x, y)`, `thickness` pixels wide.
+    pub fn draw_circle_lines(
+        &mut self,
+        x: i32,
+        y: i32,
+        radius: u32,
+        thickness: u32,
+        colour: Colour,
+    ) {
+        self.draw_ellipse_lines(x, y, radius, radius, thickness, colour);
+    }
+
+    /// Draw a filled ellipse centred on `(x, y)`.
+    pub fn draw_ellipse(&mut self, x: i32, y: i32, radius_x: u32, radius_y: u32, colour: Colour) {
+        self.draw_ellipse_lines(x, y, radius_x, radius_y, u32::MAX, colour);
+    }
+
+    /// Draw the outline of an ellipse centred on `(x, y)`, `thickness` pixels wide.
+    pub fn draw_ellipse_lines(
+        &mut self,
+        x: i32,
+        y: i32,
+        radius_x: u32,
+        radius_y: u32,
+        thickness: u32,
+        colour: Colour,
+    ) {
+        if thickness == 0 {
+            return;
+        }
+        if let Some(camera) = self.turned_camera() {
+            let centre = camera.pixel_to_screen(x, y);
+            let zoom = camera.zoom();
+            let (radius_x, radius_y) =
+                (radius_x.saturating_mul(zoom), radius_y.saturating_mul(zoom));
+            let thickness = thickness.saturating_mul(zoom);
+            self.without_camera(|canvas| {
+                canvas
+                    .draw_ellipse_lines(centre.x, centre.y, radius_x, radius_y, thickness, colour);
+            });
+            return;
+        }
+        let radius_x = i32::try_from(radius_x).unwrap_or(i32::MAX);
+        let radius_y = i32::try_from(radius_y).unwrap_or(i32::MAX);
+        let (left, right) = (x.saturating_sub(radius_x), x.saturating_add(radius_x));
+        let (top, bottom) = (y.saturating_sub(radius_y), y.saturating_add(radius_y));
+        if !self.overlaps(left, top, right, bottom) {
+            return;
+        }
+        self.mark_dirty_world(left, top, right, bottom);
+        // the ellipse left empty inside the outline, if any; thicker than any radius can be is
+        // always filled, even when the radii are cut down to fit
+        let inner = i32::try_from(thickness)
+            .ok()
+            .filter(|&thickness| thickness <= radius_x.min(radius_y))
+            .map(|thickness| (radius_x - thickness, radius_y - thickness));
+        let (_, first_row, _, last_row) = self.visible_bounds();
+        let first = (-radius_y).max(top.max(first_row).saturating_sub(y));
+        let last = radius_y.min(last_row.saturating_sub(y));
+        for dy in first..=last {
+            let row = y.saturating_add(dy);
+            let outer = ellipse_half_width(radius_x, radius_y, dy);
+            let (left, right) = (x.saturating_sub(outer), x.saturating_add(outer));
+            match inner {
+                Some((inner_x, inner_y)) if dy.abs() <= inner_y => {
+                    let inner = ellipse_half_width(inner_x, inner_y, dy);
+                    let inner_left = x.saturating_sub(inner).saturating_sub(1);
+                    let inner_right = x.saturating_add(inner).saturating_add(1);
+                    self.fill_world_span(row, left, inner_left, colour);
+                    self.fill_world_span(row, inner_right, right, colour);
+                }
+                _ => self.fill_world_span(row, left, right, colour),
+            }
+        }
+    }
+
+    /// Draw a filled rectangle with its top left corner at `(x, y)` and corners rounded like
+    /// circles of `radius`, which is cut down to fit.
+    ///
+    /// Nothing is drawn unless `w` and `h` are positive.
+    pub fn draw_rounded_rectangle(
+        &mut self,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+        radius: u32,
+        colour: Colour,
+    ) {
+        if w <= 0 || h <= 0 {
+            return;
+        }
+        self.draw_polygon(&rounded_rectangle(x, y, w, h, radius), colour);
+    }
+
+    /// Draw the outline of a rectangle with its top left corner at `(x, y)` and corners rounded
+    /// like circles of `radius`, `thickness` pixels wide inside its edges. Outlines too thick to
+    /// leave a hole are drawn filled.
+    ///
+    /// Nothing is drawn unless `w` and `h` are positive.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_rounded_rectangle_lines(
+        &mut self,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+        radius: u32,
+        thickness: u32,
+        colour: Colour,
+    ) {
+        if w <= 0 || h <= 0 || thickness == 0 {
+            return;
+        }
+        let inset = i32::try_from(thickness).unwrap_or(i32::MAX);
+        if inset.saturating_mul(2) >= w.min(h) {
+            self.draw_rounded_rectangle(x, y, w, h, radius, colour);
+            return;
+        }
+        let mut points = rounded_rectangle(x, y, w, h, radius);
+        let inner = rounded_rectangle(
+            x.saturating_add(inset),
+            y.saturating_add(inset),
+            w - 2 * inset,
+            h - 2 * inset,
+            radius.saturating_sub(thickness),
+        );
+        // cross to the inside and back along the same line, which cancels out, so the inside is
+        // enclosed twice and left empty
+        points.push(points[0]);
+        points.extend(&inner);
+        points.push(inner[0]);
+        self.draw_polygon(&points, colour);
+    }
+
+    /// Draw a filled triangle.
+    pub fn draw_triangle(&mut self, a: IVec2, b: IVec2, c: IVec2, colour: Colour) {
+        self.draw_polygon(&[a, b, c], colour);
+    }
+
+    /// Draw the outline of a triangle, `thickness` pixels wide.
+    pub fn draw_triangle_lines(
+        &mut self,
+        a: IVec2,
+        b: IVec2,
+        c: IVec2,
+        thickness: u32,
+        colour: Colour,
+    ) {
+        self.draw_polygon_lines(&[a, b, c], thickness, colour);
+    }
+
+    /// Draw a filled polygon, which may be concave or cross itself. Areas enclosed an odd number
+    /// of times are filled.
+    ///
+    /// Points lie on the corners of pixels, so the square through `(0, 0)`, `(2, 0)`, `(2, 2)`
+    /// and `(0, 2)` covers the same pixels as `draw_rectangle(0, 0, 2, 2, colour)`.
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    pub fn draw_polygon(&mut self, points: &[IVec2], colour: Colour) {
+        if points.len() < 3 {
+            return;
+        }
+        if let Some(camera) = self.turned_camera() {
+            let points: Vec<_> = points
+                .iter()
+                .map(|point| camera.corner_to_screen(point.x, point.y))
+                .collect();
+            self.without_camera(|canvas| canvas.draw_polygon(&points, colour));
+            return;
+        }
+        let min = points
+            .iter()
+            .copied()
+            .reduce(IVec2::min)
+            .unwrap_or_default();
+        let max = points
+            .iter()
+            .copied()
+            .reduce(IVec2::max)
+            .unwrap_or_default();
+        let (right, bottom) = (max.x.saturating_sub(1), max.y.saturating_sub(1));
+        if !self.overlaps(min.x, min.y, right, bottom) {
+            return;
+        }
+        self.mark_dirty_world(min.x, min.y, right, bottom);
+        let (_, first_row, _, last_row) = self.visible_bounds();
+        let mut crossings = Vec::new();
+        for y in min.y.max(first_row)..max.y.min(last_row.saturating_add(1)) {
+            // sample through the centres of the pixels in the row
+            let centre = y as f32 + 0.5;
+            crossings.clear();
+            for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+                let (a, b) = if a.y <= b.y { (a, b) } else { (b, a) };
+                if (a.y as f32) < centre && centre < b.y as f32 {
+                    let along = (centre - a.y as f32) / (b.y as f32 - a.y as f32);
+                    crossings.push((b.x as f32 - a.x as f32).mul_add(along, a.x as f32));
+                }
+            }
+            crossings.sort_by(f32::total_cmp);
+            for pair in crossings.chunks_exact(2) {
+                let left = (pair[0] - 0.5).ceil() as i32;
+                let right = (pair[1] - 0.5).ceil() as i32 - 1;
+                self.fill_world_span(y, left, right, colour);
+            }
+        }
+    }
+
+    /// Draw the outline of a polygon, `thickness` pixels wide, joining the last point back to
+    /// the first.
+    pub fn draw_polygon_lines(&mut self, points: &[IVec2], thickness: u32, colour: Colour) {
+        for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+            self.draw_line(a.x, a.y, b.x, b.y, thickness, colour);
+        }
+    }
+
+    /// Whether any of the pixels from `(left, top)` to `(right, bottom)` inclusive can appear on
+    /// the canvas.
+    fn overlaps(&self, left: i32, top: i32, right: i32, bottom: i32) -> bool {
+        let (visible_left, visible_top, visible_right, visible_bottom) = self.visible_bounds();
+        right >= visible_left
+            && bottom >= visible_top
+            && right >= left
+            && bottom >= top
+            && left <= visible_right
+            && top <= visible_bottom
+    }
+}
+
+/// Get the corners of the pixels around the edge of a rectangle with positive size and corners
+/// rounded like circles of `radius`, clockwise from the top left, for drawing as a polygon.
+fn rounded_rectangle(x: i32, y: i32, w: i32, h: i32, radius: u32) -> Vec<IVec2> {
+    let radius = i32::try_from(radius)
+        .unwrap_or(i32::MAX)
+        .min((w.min(h) - 1) / 2);
+    // how far down each row of the top corners is and how far in from the sides it starts,
+    // which the bottom corners mirror
+    let rows: Vec<_> = (0..radius)
+        .map(|row| {
+            (
+                row,
+                radius - ellipse_half_width(radius, radius, radius - row),
+            )
+        })
+        .collect();
+    let (right, bottom) = (x.saturating_add(w), y.saturating_add(h));
+    let mut points = Vec::with_capacity(rows.len() * 8 + 4);
+    for &(row, inset) in &rows {
+        points.extend([
+            IVec2::new(right.saturating_sub(inset), y.saturating_add(row)),
+            IVec2::new(right.saturating_sub(inset), y.saturating_add(row + 1)),
+        ]);
+    }
+    points.extend([
+        IVec2::new(right, y.saturating_add(radius)),
+        IVec2::new(right, bottom.saturating_sub(radius)),
+    ]);
+    for &(row, inset) in rows.iter().rev() {
+        points.extend([
+            IVec2::new(right.saturating_sub(inset), bottom.saturating_sub(row + 1)),
+            IVec2::new(right.saturating_sub(inset), bottom.saturating_sub(row)),
+        ]);
+    }
+    for &(row, inset) in &rows {
+        points.extend([
+            IVec2::new(x.saturating_add(inset), bottom.saturating_sub(row)),
+            IVec2::new(x.saturating_add(inset), bottom.saturating_sub(row + 1)),
+        ]);
+    }
+    points.extend([
+        IVec2::new(x, bottom.saturating_sub(radius)),
+        IVec2::new(x, y.saturating_add(radius)),
+    ]);
+    for &(row, inset) in rows.iter().rev() {
+        points.extend([
+            IVec2::new(x.saturating_add(inset), y.saturating_add(row + 1)),
+            IVec2::new(x.saturating_add(inset), y.saturating_add(row)),
+        ]);
+    }
+    points
+}
+
+/// Half the width of the row `dy` pixels from the centre of an ellipse, in whole pixels.
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+fn ellipse_half_width(radius_x: i32, radius_y: i32, dy: i32) -> i32 {
+    // slightly larger radii round off the single pixels otherwise left at the ends of each axis
+    let (radius_x, radius_y) = (radius_x as f32 + 0.25, radius_y as f32 + 0.25);
+    let t = dy as f32 / radius_y;
+    (radius_x * t.mul_add(-t, 1.).max(0.).sqrt()) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::colour::{BLACK, RED};
+    use super::super::testing::{screen, SIZE};
+    use super::super::{clear_background, get_pixel};
+    use super::*;
+
+    #[test]
+    fn draw_line_includes_both_ends() {
+        let _screen = screen();
+        draw_line(1, 1, 4, 4, 1, RED);
+        for i in 1..=4 {
+            assert_eq!(get_pixel(i, i), Some(RED));
+        }
+        assert_eq!(get_pixel(0, 0), Some(BLACK));
+        assert_eq!(get_pixel(5, 5), Some(BLACK));
+        assert_eq!(get_pixel(2, 1), Some(BLACK));
+    }
+
+    #[test]
+    fn draw_line_widens_across_the_line() {
+        let _screen = screen();
+        draw_line(2, 5, 10, 5, 3, RED);
+        for y in 4..=6 {
+            assert_eq!(get_pixel(2, y), Some(RED));
+            assert_eq!(get_pixel(10, y), Some(RED));
+        }
+        assert_eq!(get_pixel(6, 3), Some(BLACK));
+        assert_eq!(get_pixel(6, 7), Some(BLACK));
+    }
+
+    #[test]
+    fn draw_line_with_ends_far_outside_the_screen() {
+        let _screen = screen();
+        draw_line(i32::MIN, 5, i32::MAX, 5, u32::MAX, RED);
+        assert!(screen_pixels().all(|colour| colour == RED));
+        clear_background(BLACK);
+        draw_line(-1_000_000_000, -1_000_000_000, i32::MAX, i32::MAX, 1, RED);
+        assert_eq!(get_pixel(0, 0), Some(RED));
+        assert_eq!(get_pixel(SIZE.1 - 1, SIZE.1 - 1), Some(RED));
+        assert_eq!(get_pixel(1, 0), Some(BLACK));
+    }
+
+    #[test]
+    fn lines_cut_off_by_the_edge_keep_their_pixels() {
+        let _screen = screen();
+        let ends = [
+            (-20, -9, 30, 13),
+            (25, -30, -3, 40),
+            (-7, 3, 40, 8),
+            (8, -50, 9, 50),
+        ];
+        // far enough in for every line to fit
+        let offset = 64;
+        for (x1, y1, x2, y2) in ends {
+            let mut whole = Canvas::new(160, 160);
+            whole.draw_line(x1 + offset, y1 + offset, x2 + offset, y2 + offset, 2, RED);
+            let mut cut = Canvas::new(SIZE.0, SIZE.1);
+            cut.draw_line(x1, y1, x2, y2, 2, RED);
+            for y in 0..SIZE.1 {
+                for x in 0..SIZE.0 {
+                    assert_eq!(
+                        cut.get_pixel(x, y),
+                        whole.get_pixel(x + offset.unsigned_abs(), y + offset.unsigned_abs()),
+                        "({x}, {y}) of the line from ({x1}, {y1}) to ({x2}, {y2})"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn draw_polygon_covers_the_pixels_inside() {
+        let _screen = screen();
+        let square = [(1, 1), (4, 1), (4, 3), (1, 3)].map(|(x, y)| IVec2::new(x, y));
+        draw_polygon(&square, RED);
+        assert_eq!(get_pixel(1, 1), Some(RED));
+        assert_eq!(get_pixel(3, 2), Some(RED));
+        assert_eq!(get_pixel(4, 2), Some(BLACK));
+        assert_eq!(get_pixel(3, 3), Some(BLACK));
+    }
+
+    #[test]
+    fn draw_polygon_with_points_at_the_limits() {
+        let _screen = screen();
+        let corners = [
+            (i32::MIN, i32::MIN),
+            (i32::MAX, i32::MIN),
+            (i32::MAX, i32::MAX),
+        ];
+        draw_polygon(&corners.map(|(x, y)| IVec2::new(x, y)), RED);
+        assert_eq!(get_pixel(SIZE.0 - 1, 0), Some(RED));
+    }
+
+    #[test]
+    fn circles_and_ellipses_at_the_limits() {
+        let _screen = screen();
+        for (x, y) in [
+            (10, 5),
+            (i32::MIN, i32::MIN),
+            (i32::MAX, i32::MAX),
+            (i32::MIN, 5),
+        ] {
+            clear_background(BLACK);
+            draw_circle(x, y, u32::MAX, RED);
+            draw_ellipse(x, y, u32::MAX, u32::MAX, RED);
+            draw_ellipse_lines(x, y, u32::MAX, 3, 1, RED);
+            draw_circle_lines(x, y, u32::MAX, 1, RED);
+        }
+        clear_background(BLACK);
+        draw_circle(10, 5, u32::MAX, RED);
+        assert!(screen_pixels().all(|colour| colour == RED));
+        clear_background(BLACK);
+        draw_circle_lines(10, 5, u32::MAX, 1, RED);
+        assert!(screen_pixels().all(|colour| colour == BLACK));
+    }
+
+    #[test]
+    fn draw_rounded_rectangle_cuts_off_its_corners() {
+        let _screen = screen();
+        draw_rounded_rectangle(0, 0, 9, 7, 3, RED);
+        assert_eq!(get_pixel(0, 0), Some(BLACK));
+        assert_eq!(get_pixel(8, 6), Some(BLACK));
+        assert_eq!(get_pixel(4, 0), Some(RED));
+        assert_eq!(get_pixel(0, 3), Some(RED));
+        assert_eq!(get_pixel(4, 3), Some(RED));
+        assert_eq!(get_pixel(9, 3), Some(BLACK));
+    }
+
+    #[test]
+    fn draw_rounded_rectangle_lines_at_the_limits() {
+        let _screen = screen();
+        draw_rounded_rectangle_lines(i32::MAX - 2, i32::MAX - 2, 40, 40, 10, 3, RED);
+        draw_rounded_rectangle_lines(i32::MIN, i32::MIN, i32::MAX, i32::MAX, 10, 3, RED);
+        assert!(screen_pixels().all(|colour| colour == BLACK));
+        draw_rounded_rectangle_lines(-20, -20, i32::MAX, i32::MAX, 2, 22, RED);
+        assert_eq!(get_pixel(1, 1), Some(RED));
+        assert_eq!(get_pixel(2, 2), Some(BLACK));
+    }
+
+    fn screen_pixels() -> impl Iterator<Item = Colour> {
+        (0..SIZE.1).flat_map(|y| (0..SIZE.0).filter_map(move |x| get_pixel(x, y)))
+    }
+}
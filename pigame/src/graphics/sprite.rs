@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+use log::warn;
+
+use crate::context::get;
+use crate::error::Result;
+
+use super::backend::Region;
+use super::canvas::Canvas;
+use super::get_frame_time;
+use super::texture::{DrawTextureParams, Texture};
+
+/// Where the frames of a sprite sheet are in its image.
+#[derive(Debug, Clone)]
+pub enum SheetLayout {
+    /// Frames of the same size packed edge to edge, numbered left to right then top to bottom.
+    /// Partial frames at the right and bottom edges are ignored.
+    Grid {
+        /// Width of each frame.
+        frame_width: u32,
+        /// Height of each frame.
+        frame_height: u32,
+    },
+    /// Frames at arbitrary positions, numbered in order.
+    Frames(Vec<Region>),
+}
+
+/// Image holding many frames, such as the poses of a character.
+#[derive(Debug, Clone)]
+pub struct SpriteSheet {
+    texture: Texture,
+    frames: Vec<Region>,
+}
+
+impl SpriteSheet {
+    /// Split `texture` into frames as described by `layout`.
+    #[must_use]
+    pub fn new(texture: Texture, layout: SheetLayout) -> Self {
+        let frames = match layout {
+            SheetLayout::Grid {
+                frame_width,
+                frame_height,
+            } => {
+                let columns = texture.width().checked_div(frame_width).unwrap_or(0);
+                let rows = texture.height().checked_div(frame_height).unwrap_or(0);
+                (0..rows)
+                    .flat_map(|row| {
+                        (0..columns).map(move |column| {
+                            Region::new(
+                                column * frame_width,
+                                row * frame_height,
+                                frame_width,
+                                frame_height,
+                            )
+                        })
+                    })
+                    .collect()
+            }
+            SheetLayout::Frames(frames) => frames,
+        };
+        Self { texture, frames }
+    }
+
+    /// Load a sprite sheet from a PNG, BMP or QOI image file.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or is not a supported image, an error is returned.
+    pub fn from_file<P: AsRef<Path>>(path: P, layout: SheetLayout) -> Result<Self> {
+        Ok(Self::new(Texture::from_file(path)?, layout))
+    }
+
+    /// Get the image the frames are taken from.
+    #[must_use]
+    pub const fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Get the part of the image showing a frame, or `None` if there is no such frame.
+    #[must_use]
+    pub fn frame(&self, index: usize) -> Option<Region> {
+        self.frames.get(index).copied()
+    }
+
+    /// Get the number of frames.
+    #[must_use]
+    pub const fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+/// What an animation does once it reaches its last frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlayMode {
+    /// Stop on the last frame.
+    Once,
+    /// Start again from the first frame.
+    #[default]
+    Loop,
+    /// Play backwards to the first frame, then forwards again, and so on.
+    PingPong,
+}
+
+/// Sequence of sprite sheet frames, each shown for its own duration.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    /// Frame numbers in the sprite sheet and how long each is shown.
+    frames: Vec<(usize, Duration)>,
+    mode: PlayMode,
+}
+
+impl Animation {
+    /// Create an animation showing each frame number for its duration.
+    #[must_use]
+    pub const fn new(frames: Vec<(usize, Duration)>, mode: PlayMode) -> Self {
+        Self { frames, mode }
+    }
+
+    /// Create an animation showing every frame for the same duration.
+    #[must_use]
+    pub fn uniform<I: IntoIterator<Item = usize>>(
+        frames: I,
+        frame_duration: Duration,
+        mode: PlayMode,
+    ) -> Self {
+        Self::new(
+            frames
+                .into_iter()
+                .map(|frame| (frame, frame_duration))
+                .collect(),
+            mode,
+        )
+    }
+}
+
+/// Sprite playing named animations from a shared sprite sheet.
+///
+/// Call [`update`](Self::update) once a frame to move the animation on, and draw it with
+/// [`draw_sprite`].
+#[derive(Debug, Clone)]
+pub struct AnimatedSprite {
+    sheet: Rc<SpriteSheet>,
+    animations: HashMap<String, Animation>,
+    current: Option<String>,
+    /// Position in the frames of the current animation.
+    position: usize,
+    /// Time the current frame has been shown for.
+    elapsed: Duration,
+    /// Whether a ping-pong animation is playing forwards.
+    forwards: bool,
+    finished: bool,
+}
+
+impl AnimatedSprite {
+    /// Create a sprite showing frames from `sheet`, which can be shared between many sprites.
+    #[must_use]
+    pub fn new(sheet: Rc<SpriteSheet>) -> Self {
+        Self {
+            sheet,
+            animations: HashMap::new(),
+            current: None,
+            position: 0,
+            elapsed: Duration::ZERO,
+            forwards: true,
+            finished: false,
+        }
+    }
+
+    /// Add an animation that can be played by `name`, replacing any with the same name.
+    pub fn add_animation<S: Into<String>>(&mut self, name: S, animation: Animation) {
+        self.animations.insert(name.into(), animation);
+    }
+
+    /// Add an animation and return the sprite, for building sprites in one expression.
+    #[must_use]
+    pub fn with_animation<S: Into<String>>(mut self, name: S, animation: Animation) -> Self {
+        self.add_animation(name, animation);
+        self
+    }
+
+    /// Switch to the animation called `name`, starting from its first frame. Does nothing if it
+    /// is already playing.
+    pub fn play(&mut self, name: &str) {
+        if self.current.as_deref() == Some(name) {
+            return;
+        }
+        if !self.animations.contains_key(name) {
+            warn!("no animation called {name}");
+            return;
+        }
+        self.current = Some(name.to_owned());
+        self.restart();
+    }
+
+    /// Start the current animation again from its first frame.
+    pub const fn restart(&mut self) {
+        self.position = 0;
+        self.elapsed = Duration::ZERO;
+        self.forwards = true;
+        self.finished = false;
+    }
+
+    /// Get the name of the animation playing, if any.
+    #[must_use]
+    pub fn current_animation(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// Return true if an animation played [`Once`](PlayMode::Once) has reached its end.
+    #[must_use]
+    pub const fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Get the number of the sprite sheet frame being shown, if an animation is playing.
+    #[must_use]
+    pub fn current_frame(&self) -> Option<usize> {
+        let animation = self.animations.get(self.current.as_deref()?)?;
+        animation.frames.get(self.position).map(|&(frame, _)| frame)
+    }
+
+    /// Move the animation on by the time since the last frame, from
+    /// [`get_frame_time`].
+    pub fn update(&mut self) {
+        self.advance(get_frame_time());
+    }
+
+    /// Move the animation on by `time`.
+    pub fn advance(&mut self, time: Duration) {
+        let Some(animation) = self
+            .current
+            .as_deref()
+            .and_then(|name| self.animations.get(name))
+        else {
+            return;
+        };
+        let frames = &animation.frames;
+        if self.finished || frames.iter().all(|(_, duration)| duration.is_zero()) {
+            return;
+        }
+        self.elapsed += time;
+        while self.elapsed >= frames[self.position].1 {
+            self.elapsed -= frames[self.position].1;
+            let last = frames.len() - 1;
+            match animation.mode {
+                PlayMode::Once if self.position == last => {
+                    self.finished = true;
+                    self.elapsed = Duration::ZERO;
+                    return;
+                }
+                PlayMode::Once => self.position += 1,
+                PlayMode::Loop => {
+                    self.position = if self.position == last {
+                        0
+                    } else {
+                        self.position + 1
+                    }
+                }
+                PlayMode::PingPong if last == 0 => {}
+                PlayMode::PingPong => {
+                    if self.position == last {
+                        self.forwards = false;
+                    } else if self.position == 0 {
+                        self.forwards = true;
+                    }
+                    if self.forwards {
+                        self.position += 1;
+                    } else {
+                        self.position -= 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draw the current frame of `sprite` on the screen with its top left corner at `(x, y)`.
+pub fn draw_sprite(sprite: &AnimatedSprite, x: i32, y: i32) {
+    get().frame_buffer.canvas.draw_sprite(sprite, x, y);
+}
+
+/// Draw the current frame of `sprite` on the screen with its top left corner at `(x, y)`, sized
+/// and flipped with `params`. The source in `params` is ignored.
+pub fn draw_sprite_ex(sprite: &AnimatedSprite, x: i32, y: i32, params: DrawTextureParams) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_sprite_ex(sprite, x, y, params);
+}
+
+impl Canvas {
+    /// Draw the current frame of `sprite` on the canvas with its top left corner at `(x, y)`.
+    pub fn draw_sprite(&mut self, sprite: &AnimatedSprite, x: i32, y: i32) {
+        self.draw_sprite_ex(sprite, x, y, DrawTextureParams::default());
+    }
+
+    /// Draw the current frame of `sprite` on the canvas with its top left corner at `(x, y)`,
+    /// sized and flipped with `params`. The source in `params` is ignored.
+    ///
+    /// Nothing is drawn if no animation is playing.
+    pub fn draw_sprite_ex(
+        &mut self,
+        sprite: &AnimatedSprite,
+        x: i32,
+        y: i32,
+        params: DrawTextureParams,
+    ) {
+        let Some(source) = sprite
+            .current_frame()
+            .and_then(|frame| sprite.sheet.frame(frame))
+        else {
+            return;
+        };
+        let params = DrawTextureParams {
+            source: Some(source),
+            ..params
+        };
+        self.draw_texture_ex(&sprite.sheet.texture, x, y, params);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::colour::{BLUE, RED};
+    use super::super::get_pixel;
+    use super::super::testing::screen;
+    use super::*;
+
+    const STEP: Duration = Duration::from_millis(100);
+
+    /// Sprite with a sheet of three 1 by 1 frames, red, blue and red, playing `animation`.
+    fn sprite(animation: Animation) -> AnimatedSprite {
+        let rgba = [255, 0, 0, 255, 0, 0, 255, 255, 255, 0, 0, 255];
+        let texture = Texture::from_rgba(3, 1, &rgba).expect("there is a pixel for each byte");
+        let layout = SheetLayout::Grid {
+            frame_width: 1,
+            frame_height: 1,
+        };
+        let mut sprite = AnimatedSprite::new(Rc::new(SpriteSheet::new(texture, layout)))
+            .with_animation("test", animation);
+        sprite.play("test");
+        sprite
+    }
+
+    /// Frames shown after each of `steps` more steps.
+    fn frames(sprite: &mut AnimatedSprite, steps: usize) -> Vec<Option<usize>> {
+        (0..steps)
+            .map(|_| {
+                sprite.advance(STEP);
+                sprite.current_frame()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame() {
+        let _screen = screen();
+        let mut sprite = sprite(Animation::uniform(0..3, STEP, PlayMode::Once));
+        assert_eq!(sprite.current_frame(), Some(0));
+        assert_eq!(frames(&mut sprite, 2), [Some(1), Some(2)]);
+        assert!(!sprite.is_finished());
+        assert_eq!(frames(&mut sprite, 3), [Some(2); 3]);
+        assert!(sprite.is_finished());
+        sprite.restart();
+        assert_eq!(sprite.current_frame(), Some(0));
+        assert!(!sprite.is_finished());
+    }
+
+    #[test]
+    fn loop_wraps_round_to_the_first_frame() {
+        let _screen = screen();
+        let mut sprite = sprite(Animation::uniform(0..3, STEP, PlayMode::Loop));
+        assert_eq!(frames(&mut sprite, 4), [Some(1), Some(2), Some(0), Some(1)]);
+        // past the end and round again in one step
+        sprite.advance(STEP * 4 + STEP / 2);
+        assert_eq!(sprite.current_frame(), Some(2));
+        sprite.advance(STEP / 2);
+        assert_eq!(sprite.current_frame(), Some(0));
+        assert!(!sprite.is_finished());
+    }
+
+    #[test]
+    fn ping_pong_turns_round_without_repeating_the_ends() {
+        let _screen = screen();
+        let mut sprite = sprite(Animation::uniform(0..3, STEP, PlayMode::PingPong));
+        assert_eq!(
+            frames(&mut sprite, 6),
+            [Some(1), Some(2), Some(1), Some(0), Some(1), Some(2)]
+        );
+        sprite.advance(STEP * 3);
+        assert_eq!(sprite.current_frame(), Some(1));
+    }
+
+    #[test]
+    fn frames_last_their_own_duration() {
+        let _screen = screen();
+        let animation = Animation::new(
+            vec![(2, STEP * 3), (0, Duration::ZERO), (1, STEP)],
+            PlayMode::Loop,
+        );
+        let mut sprite = sprite(animation);
+        assert_eq!(sprite.current_frame(), Some(2));
+        assert_eq!(
+            frames(&mut sprite, 5),
+            [Some(2), Some(2), Some(1), Some(2), Some(2)]
+        );
+    }
+
+    #[test]
+    fn animations_without_time_to_play_stay_put() {
+        let _screen = screen();
+        let mut empty = sprite(Animation::new(Vec::new(), PlayMode::Loop));
+        empty.advance(STEP);
+        assert_eq!(empty.current_frame(), None);
+        for mode in [PlayMode::Once, PlayMode::Loop, PlayMode::PingPong] {
+            let mut sprite = sprite(Animation::uniform(0..3, Duration::ZERO, mode));
+            sprite.advance(STEP);
+            assert_eq!(sprite.current_frame(), Some(0));
+        }
+    }
+
+    #[test]
+    fn draw_sprite_draws_the_current_frame() {
+        let _screen = screen();
+        let mut sprite = sprite(Animation::uniform(0..3, STEP, PlayMode::Loop));
+        draw_sprite(&sprite, 0, 0);
+        sprite.advance(STEP);
+        draw_sprite(&sprite, 1, 0);
+        assert_eq!(get_pixel(0, 0), Some(RED));
+        assert_eq!(get_pixel(1, 0), Some(BLUE));
+    }
+}
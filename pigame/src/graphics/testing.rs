@@ -0,0 +1,64 @@
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+use fontdue::FontSettings;
+
+use crate::context::get;
+
+use super::backend::HeadlessBackend;
+use super::camera::reset_camera;
+use super::colour::{BlendMode, BLACK};
+use super::palette::reset_palette;
+use super::text::load_ttf_font;
+use super::{
+    clear_background, reset_logical_resolution, set_blend_mode, set_border_colour,
+    set_dirty_tracking, set_display_backend, set_screen_flip, set_screen_rotation, Rotation,
+};
+
+/// Resolution of the headless display tests draw on.
+pub(super) const SIZE: (u32, u32) = (16, 12);
+
+/// The screen is global, so tests that use it take turns.
+static LOCK: Mutex<()> = Mutex::new(());
+
+static DISPLAY: OnceLock<HeadlessBackend> = OnceLock::new();
+
+static FONT: OnceLock<usize> = OnceLock::new();
+
+/// Wait for the screen to be free, then get it cleared to black with everything set back to how
+/// it starts, along with the headless display it is shown on. The screen is free again once the
+/// guard is dropped.
+pub(super) fn screen() -> (MutexGuard<'static, ()>, &'static HeadlessBackend) {
+    let guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let display = DISPLAY.get_or_init(|| {
+        let display = HeadlessBackend::new(SIZE.0, SIZE.1);
+        set_display_backend(display.clone()).expect("the screen is only set up here");
+        display
+    });
+    reset_logical_resolution();
+    set_screen_rotation(Rotation::None);
+    set_screen_flip(false, false);
+    set_dirty_tracking(true);
+    set_blend_mode(BlendMode::Alpha);
+    set_border_colour(BLACK);
+    reset_camera();
+    reset_palette();
+    let context = get();
+    context.frame_buffer.deferred.clear();
+    context.recorder = None;
+    clear_background(BLACK);
+    (guard, display)
+}
+
+/// Get the font used by tests, loading it the first time. Only call with the screen taken.
+pub(super) fn font() -> usize {
+    *FONT.get_or_init(|| {
+        load_ttf_font(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/graphics/Quinque Five Font.ttf"
+            ),
+            FontSettings::default(),
+        )
+        .expect("the font is in the repository")
+    })
+}
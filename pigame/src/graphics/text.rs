@@ -4,6 +4,7 @@ pub use fontdue::{Font, FontSettings};
 
 use crate::{context::get, error::Error};
 
+use super::canvas::Canvas;
 use super::colour::Colour;
 
 /// Load a ttf font and return the index of the font in the internal font list.
@@ -18,26 +19,84 @@ pub fn load_ttf_font<P: AsRef<Path>>(path: P, settings: FontSettings) -> Result<
 }
 
 /// Draw text to the screen at the specified position.
-#[allow(unused_variables)]
-pub fn draw_text_ex(text: &str, x: u32, y: u32, font: usize, size: f32, colour: Colour) {
-    let font = &get().fonts[font];
-    let frame_buffer = &mut get().frame_buffer;
-    for char in text.chars() {
-        let (metrics, raster) = font.rasterize(char, font.scale_factor(size));
-        let rows = raster.chunks_exact(metrics.width);
-        for (dy, row) in rows.enumerate() {
-            for (dx, pixel) in row.iter().enumerate() {
-                let start = ((y as usize + dy) * frame_buffer.variable_info.xres as usize
-                    + x as usize
-                    + dx)
-                    * 4;
-                let slice = frame_buffer.buffer.get_mut(start..start + 4);
-                if let Some(slice) = slice {
-                    slice.copy_from_slice(&(colour * ((f32::from(*pixel)) / 255.)).to_bgra_bytes());
-                } else {
-                    break;
+///
+/// Parts falling outside the screen are cut off.
+pub fn draw_text_ex(text: &str, x: i32, y: i32, font: usize, size: f32, colour: Colour) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_text_ex(text, x, y, font, size, colour);
+}
+
+impl Canvas {
+    /// Draw text on the canvas at the specified position, with a font loaded by
+    /// [`load_ttf_font`]. Edges are antialiased by blending with what is underneath.
+    ///
+    /// A camera that turns the world moves and scales text but does not turn it.
+    pub fn draw_text_ex(
+        &mut self,
+        text: &str,
+        x: i32,
+        y: i32,
+        font: usize,
+        size: f32,
+        colour: Colour,
+    ) {
+        if let Some(camera) = self.turned_camera() {
+            let corner = camera.corner_to_screen(x, y);
+            #[allow(clippy::cast_precision_loss)]
+            let size = size * camera.zoom() as f32;
+            self.without_camera(|canvas| {
+                canvas.draw_text_ex(text, corner.x, corner.y, font, size, colour);
+            });
+            return;
+        }
+        let font = &get().fonts[font];
+        for char in text.chars() {
+            let (metrics, raster) = font.rasterize(char, font.scale_factor(size));
+            if metrics.width == 0 {
+                continue;
+            }
+            #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+            self.mark_dirty_world(
+                x,
+                y,
+                x.saturating_add(metrics.width as i32 - 1),
+                y.saturating_add(metrics.height as i32 - 1),
+            );
+            let rows = raster.chunks_exact(metrics.width);
+            #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+            for (dy, row) in rows.enumerate() {
+                for (dx, coverage) in row.iter().enumerate() {
+                    // coverage of the pixel by the glyph makes it that much more transparent
+                    let alpha = (u32::from(colour.alpha) * u32::from(*coverage) + 127) / 255;
+                    let (x, y) = (x.saturating_add(dx as i32), y.saturating_add(dy as i32));
+                    self.fill_world_span(y, x, x, colour.with_alpha(alpha as u8));
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::colour::{BLACK, WHITE};
+    use super::super::get_pixel;
+    use super::super::testing::{font, screen, SIZE};
+    use super::*;
+
+    #[test]
+    fn draw_text_ex_draws_the_glyph_below_and_right_of_its_position() {
+        let _screen = screen();
+        // the size is scaled by the 2048 units per em of the font, giving glyphs 8 pixels high
+        draw_text_ex("H", 4, 2, font(), 8. * 2048., WHITE);
+        let lit = |columns: std::ops::Range<u32>, rows: std::ops::Range<u32>| {
+            rows.flat_map(|y| columns.clone().map(move |x| (x, y)))
+                .filter(|&(x, y)| get_pixel(x, y) != Some(BLACK))
+                .count()
+        };
+        assert!(lit(4..SIZE.0, 2..SIZE.1) > 0);
+        assert_eq!(lit(0..SIZE.0, 0..2), 0);
+        assert_eq!(lit(0..4, 0..SIZE.1), 0);
+    }
+}
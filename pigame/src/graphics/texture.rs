@@ -0,0 +1,423 @@
+mod bmp;
+mod qoi;
+
+use std::fmt::{self, Debug, Formatter};
+use std::fs::read;
+use std::path::Path;
+
+use glam::{IVec2, Vec2};
+use png::{ColorType, Decoder, Transformations};
+
+use crate::context::get;
+use crate::error::{Error, Result};
+
+use super::backend::{PixelFormat, Region};
+use super::camera::Camera2D;
+use super::canvas::Canvas;
+use super::colour::{BlendMode, Colour};
+use super::palette::PixelEncoding;
+
+/// Image loaded into memory in the pixel format of the display, ready to be drawn.
+#[derive(Clone)]
+pub struct Texture {
+    width: u32,
+    height: u32,
+    pixel_format: PixelFormat,
+    pixels: Vec<u8>,
+    /// Alpha of each pixel, if any are not fully opaque.
+    alpha: Option<Vec<u8>>,
+}
+
+impl Debug for Texture {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Texture")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("pixel_format", &self.pixel_format)
+            .field("transparent", &self.alpha.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Texture {
+    /// Load a PNG, BMP or QOI image file.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or is not a supported image, an error is returned.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_bytes(&read(path)?)
+    }
+
+    /// Load a PNG, BMP or QOI image from the bytes of its file, such as those from
+    /// [`include_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// If the bytes are not a supported image, an error is returned.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (width, height, rgba) = if bytes.starts_with(b"\x89PNG") {
+            decode_png(bytes)?
+        } else if bytes.starts_with(b"BM") {
+            bmp::decode(bytes)?
+        } else if bytes.starts_with(b"qoif") {
+            qoi::decode(bytes)?
+        } else {
+            return Err(Error::InvalidImage("unrecognised image format"));
+        };
+        Self::from_rgba(width, height, &rgba)
+    }
+
+    /// Create a texture from RGBA bytes, 4 per pixel, row by row from the top left.
+    ///
+    /// # Errors
+    ///
+    /// If there are not exactly `width * height * 4` bytes, an error is returned.
+    pub fn from_rgba(width: u32, height: u32, rgba: &[u8]) -> Result<Self> {
+        if rgba.len() as u64 != u64::from(width) * u64::from(height) * 4 {
+            return Err(Error::InvalidImage("wrong number of bytes for image size"));
+        }
+        let pixel_format = get().frame_buffer.canvas.pixel_format;
+        let bytes_per_pixel = pixel_format.bytes_per_pixel();
+        let mut pixels = Vec::with_capacity(rgba.len() / 4 * bytes_per_pixel);
+        let mut alpha = Vec::with_capacity(rgba.len() / 4);
+        for pixel in rgba.chunks_exact(4) {
+            let colour = Colour::rgba(pixel[0], pixel[1], pixel[2], pixel[3]);
+            pixels.extend_from_slice(&pixel_format.pack(colour)[..bytes_per_pixel]);
+            alpha.push(pixel[3]);
+        }
+        let opaque = alpha.iter().all(|&alpha| alpha == u8::MAX);
+        Ok(Self {
+            width,
+            height,
+            pixel_format,
+            pixels,
+            alpha: (!opaque).then_some(alpha),
+        })
+    }
+
+    /// Make every pixel of colour `key` fully transparent, for images without an alpha channel
+    /// that mark the background with a colour instead.
+    #[must_use]
+    pub fn with_colour_key(mut self, key: Colour) -> Self {
+        let bytes_per_pixel = self.pixel_format.bytes_per_pixel();
+        // compare packed pixels so colours rounded the same way by the pixel format match
+        let key = self.pixel_format.pack(key);
+        let key = &key[..bytes_per_pixel];
+        let pixel_count = self.pixels.len() / bytes_per_pixel;
+        let alpha = self.alpha.get_or_insert_with(|| vec![u8::MAX; pixel_count]);
+        for (pixel, alpha) in self.pixels.chunks_exact(bytes_per_pixel).zip(alpha) {
+            if pixel == key {
+                *alpha = 0;
+            }
+        }
+        self
+    }
+
+    /// Get the width of the texture.
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the texture.
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// Options for [`draw_texture_ex`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DrawTextureParams {
+    /// Part of the texture to draw; all of it if `None`.
+    pub source: Option<Region>,
+    /// Size to stretch or shrink the drawn part of the texture to; its own size if `None`.
+    pub dest_size: Option<(u32, u32)>,
+    /// Whether to mirror the texture horizontally.
+    pub flip_x: bool,
+    /// Whether to mirror the texture vertically.
+    pub flip_y: bool,
+}
+
+/// Draw all of `texture` on the screen with its top left corner at `(x, y)`.
+pub fn draw_texture(texture: &Texture, x: i32, y: i32) {
+    get().frame_buffer.canvas.draw_texture(texture, x, y);
+}
+
+/// Draw `texture` on the screen with its top left corner at `(x, y)`, choosing which part to
+/// draw, its size and flipping with `params`.
+pub fn draw_texture_ex(texture: &Texture, x: i32, y: i32, params: DrawTextureParams) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_texture_ex(texture, x, y, params);
+}
+
+impl Canvas {
+    /// Draw all of `texture` on the canvas with its top left corner at `(x, y)`.
+    pub fn draw_texture(&mut self, texture: &Texture, x: i32, y: i32) {
+        self.draw_texture_ex(texture, x, y, DrawTextureParams::default());
+    }
+
+    /// Draw `texture` on the canvas with its top left corner at `(x, y)`, choosing which part to
+    /// draw, its size and flipping with `params`.
+    ///
+    /// Scaled textures are sampled without smoothing, keeping pixel art sharp. Transparent pixels
+    /// are blended using the blend mode of the canvas, and parts falling outside the canvas are
+    /// cut off. A camera that turns the world turns the texture with it.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn draw_texture_ex(
+        &mut self,
+        texture: &Texture,
+        x: i32,
+        y: i32,
+        params: DrawTextureParams,
+    ) {
+        let source = params
+            .source
+            .unwrap_or_else(|| Region::new(0, 0, texture.width, texture.height))
+            .clip(texture.width, texture.height);
+        let (width, height) = params.dest_size.unwrap_or((source.width, source.height));
+        if source.is_empty() || width == 0 || height == 0 {
+            return;
+        }
+        if let Some(camera) = self.turned_camera() {
+            self.draw_texture_turned(texture, source, (x, y, width, height), params, &camera);
+            return;
+        }
+        let IVec2 { x, y } = self.corner_to_canvas(x, y);
+        let (width, height) = (self.zoom_length(width), self.zoom_length(height));
+        let right = x.saturating_add(i32::try_from(width - 1).unwrap_or(i32::MAX));
+        let bottom = y.saturating_add(i32::try_from(height - 1).unwrap_or(i32::MAX));
+        self.mark_dirty_bounds(x, y, right, bottom);
+        let first_column = x.max(0);
+        let last_column = right.min(i32::try_from(self.width).unwrap_or(i32::MAX) - 1);
+        let first_row = y.max(0);
+        let last_row = bottom.min(i32::try_from(self.height).unwrap_or(i32::MAX) - 1);
+        if first_column > last_column || first_row > last_row {
+            return;
+        }
+
+        // the source pixel shown at each distance from the left or top of where it is drawn
+        let sample =
+            |offset: i32, start: i32, size: u32, source_start: u32, source_size: u32, flip| {
+                let along = (i64::from(offset) - i64::from(start)) as u64 * u64::from(source_size)
+                    / u64::from(size);
+                let along = if flip {
+                    u64::from(source_size) - 1 - along
+                } else {
+                    along
+                };
+                source_start as usize + along as usize
+            };
+        let columns: Vec<usize> = (first_column..=last_column)
+            .map(|column| sample(column, x, width, source.x, source.width, params.flip_x))
+            .collect();
+        let encoding = PixelEncoding::new(self.pixel_format, self.palette.as_deref());
+        let bytes_per_pixel = encoding.bytes_per_pixel();
+        let texture_bytes_per_pixel = texture.pixel_format.bytes_per_pixel();
+        let copyable = texture.pixel_format == self.pixel_format
+            && self.palette.is_none()
+            && self.blend_mode == BlendMode::Alpha;
+        for row in first_row..=last_row {
+            let source_row = sample(row, y, height, source.y, source.height, params.flip_y)
+                * texture.width as usize;
+            let start = self.pixel_range(first_column as usize, row as usize).start;
+            let destination = &mut self.buffer[start..start + columns.len() * bytes_per_pixel];
+            for (pixel, &column) in destination.chunks_exact_mut(bytes_per_pixel).zip(&columns) {
+                let index = source_row + column;
+                let alpha = texture.alpha.as_ref().map_or(u8::MAX, |alpha| alpha[index]);
+                if alpha == 0 {
+                    continue;
+                }
+                let source_pixel = &texture.pixels
+                    [index * texture_bytes_per_pixel..(index + 1) * texture_bytes_per_pixel];
+                if copyable && alpha == u8::MAX {
+                    pixel.copy_from_slice(source_pixel);
+                    continue;
+                }
+                let colour = texture
+                    .pixel_format
+                    .unpack(source_pixel)
+                    .with_alpha(alpha)
+                    .blend(encoding.unpack(pixel), self.blend_mode);
+                pixel.copy_from_slice(&encoding.pack(colour)[..bytes_per_pixel]);
+            }
+        }
+    }
+
+    /// Draw `source` of `texture` stretched over the rectangle `(x, y, width, height)` in the
+    /// world, turned by `camera`, sampling the texture at the middle of each pixel covered.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn draw_texture_turned(
+        &mut self,
+        texture: &Texture,
+        source: Region,
+        (x, y, width, height): (i32, i32, u32, u32),
+        params: DrawTextureParams,
+        camera: &Camera2D,
+    ) {
+        let (x, y) = (x as f32, y as f32);
+        let (width, height) = (width as f32, height as f32);
+        let corners = [
+            (x, y),
+            (x + width, y),
+            (x, y + height),
+            (x + width, y + height),
+        ]
+        .map(|(x, y)| camera.world_to_screen(Vec2::new(x, y)));
+        let min = corners.into_iter().reduce(Vec2::min).unwrap_or_default();
+        let max = corners.into_iter().reduce(Vec2::max).unwrap_or_default();
+        let (left, top) = (min.x.floor() as i32, min.y.floor() as i32);
+        let (right, bottom) = (
+            (max.x.ceil() as i32).saturating_sub(1),
+            (max.y.ceil() as i32).saturating_sub(1),
+        );
+        self.mark_dirty_bounds(left, top, right, bottom);
+        let last_column = i32::try_from(self.width).unwrap_or(i32::MAX) - 1;
+        let last_row = i32::try_from(self.height).unwrap_or(i32::MAX) - 1;
+        let bytes_per_pixel = texture.pixel_format.bytes_per_pixel();
+        // the source pixel `offset` across a side `size` long, or `None` if off the end
+        let sample = |offset: f32, size: f32, source_start: u32, source_size: u32, flip| {
+            if !(0. ..size).contains(&offset) {
+                return None;
+            }
+            let along = ((offset / size * source_size as f32) as u32).min(source_size - 1);
+            let along = if flip { source_size - 1 - along } else { along };
+            Some((source_start + along) as usize)
+        };
+        for row in top.max(0)..=bottom.min(last_row) {
+            for column in left.max(0)..=right.min(last_column) {
+                let world = camera.screen_to_world(Vec2::new(column as f32, row as f32) + 0.5);
+                let Some((source_x, source_y)) =
+                    sample(world.x - x, width, source.x, source.width, params.flip_x).zip(sample(
+                        world.y - y,
+                        height,
+                        source.y,
+                        source.height,
+                        params.flip_y,
+                    ))
+                else {
+                    continue;
+                };
+                let index = source_y * texture.width as usize + source_x;
+                let alpha = texture.alpha.as_ref().map_or(u8::MAX, |alpha| alpha[index]);
+                let colour = texture
+                    .pixel_format
+                    .unpack(&texture.pixels[index * bytes_per_pixel..(index + 1) * bytes_per_pixel])
+                    .with_alpha(alpha);
+                self.fill_span(row, column, column, colour);
+            }
+        }
+    }
+}
+
+/// Decode a PNG image into its size and RGBA bytes.
+fn decode_png(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+    let mut decoder = Decoder::new(bytes);
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    buffer.truncate(info.buffer_size());
+    let rgba = match info.color_type {
+        ColorType::Rgba => buffer,
+        ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], u8::MAX])
+            .collect(),
+        ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+            .collect(),
+        ColorType::Grayscale => buffer
+            .iter()
+            .flat_map(|&grey| [grey, grey, grey, u8::MAX])
+            .collect(),
+        ColorType::Indexed => return Err(Error::InvalidImage("unexpanded png palette")),
+    };
+    Ok((info.width, info.height, rgba))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::camera::set_camera;
+    use super::super::colour::{BLACK, BLUE, RED};
+    use super::super::get_pixel;
+    use super::super::testing::screen;
+    use super::*;
+
+    /// QOI image 2 by 1 pixels, red then blue.
+    const RED_BLUE: &[u8] = &[
+        b'q', b'o', b'i', b'f', 0, 0, 0, 2, 0, 0, 0, 1, 4, 0, //
+        0xff, 255, 0, 0, 255, //
+        0xfe, 0, 0, 255, //
+        0, 0, 0, 0, 0, 0, 0, 1,
+    ];
+
+    #[test]
+    fn qoi_images_load_and_draw() {
+        let _screen = screen();
+        let texture = Texture::from_bytes(RED_BLUE).expect("the image is valid");
+        assert_eq!((texture.width(), texture.height()), (2, 1));
+        draw_texture(&texture, 3, 4);
+        assert_eq!(get_pixel(3, 4), Some(RED));
+        assert_eq!(get_pixel(4, 4), Some(BLUE));
+        assert_eq!(get_pixel(5, 4), Some(BLACK));
+    }
+
+    #[test]
+    fn qoi_images_larger_than_their_bytes_are_rejected() {
+        let _screen = screen();
+        let mut bytes = RED_BLUE.to_vec();
+        bytes[4..12].copy_from_slice(&[0, 0, 0x40, 0, 0, 0, 0x40, 0]);
+        assert!(Texture::from_bytes(&bytes).is_err());
+        assert!(Texture::from_bytes(&RED_BLUE[..16]).is_err());
+    }
+
+    #[test]
+    fn draw_texture_ex_stretches_and_flips() {
+        let _screen = screen();
+        let texture = Texture::from_bytes(RED_BLUE).expect("the image is valid");
+        let params = DrawTextureParams {
+            dest_size: Some((4, 2)),
+            flip_x: true,
+            ..DrawTextureParams::default()
+        };
+        draw_texture_ex(&texture, 0, 0, params);
+        for y in 0..2 {
+            assert_eq!(get_pixel(0, y), Some(BLUE));
+            assert_eq!(get_pixel(1, y), Some(BLUE));
+            assert_eq!(get_pixel(2, y), Some(RED));
+            assert_eq!(get_pixel(3, y), Some(RED));
+        }
+        assert_eq!(get_pixel(0, 2), Some(BLACK));
+    }
+
+    #[test]
+    fn transparent_pixels_leave_the_screen_showing() {
+        let _screen = screen();
+        let texture = Texture::from_rgba(2, 1, &[255, 0, 0, 255, 0, 0, 255, 0])
+            .expect("there is a pixel for each byte");
+        draw_texture(&texture, -1, 0);
+        assert_eq!(get_pixel(0, 0), Some(BLACK));
+        draw_texture(&texture, 0, 0);
+        assert_eq!(get_pixel(0, 0), Some(RED));
+        assert_eq!(get_pixel(1, 0), Some(BLACK));
+    }
+
+    #[test]
+    fn turned_textures_far_off_the_screen_draw_nothing() {
+        let _screen = screen();
+        let texture = Texture::from_bytes(RED_BLUE).expect("the image is valid");
+        set_camera(&Camera2D::default().with_rotation(0.5));
+        draw_texture(&texture, i32::MIN, i32::MIN);
+        draw_texture(&texture, i32::MAX, i32::MAX);
+        assert_eq!(get_pixel(0, 0), Some(BLACK));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::error::{Error, Result};
+
+/// Compression values for uncompressed pixels, optionally with channel masks.
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or(Error::InvalidImage("truncated bmp"))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or(Error::InvalidImage("truncated bmp"))
+}
+
+/// Bit mask of one channel in a 16 or 32 bit pixel.
+#[derive(Clone, Copy)]
+struct Mask(u32);
+
+impl Mask {
+    #[allow(clippy::cast_possible_truncation)]
+    fn extract(self, pixel: u32) -> Option<u8> {
+        if self.0 == 0 {
+            return None;
+        }
+        let shift = self.0.trailing_zeros();
+        let max = u64::from(self.0 >> shift);
+        let value = u64::from((pixel & self.0) >> shift);
+        Some((value * 255 / max) as u8)
+    }
+}
+
+/// Decode an uncompressed BMP image into its size and RGBA bytes.
+///
+/// 1, 4 and 8 bit palette images and 16, 24 and 32 bit colour images are supported, with or
+/// without channel masks.
+pub(super) fn decode(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+    let data_offset = u32_at(bytes, 10)? as usize;
+    let header_size = u32_at(bytes, 14)? as usize;
+    if header_size < 40 {
+        return Err(Error::InvalidImage("unsupported bmp header"));
+    }
+    #[allow(clippy::cast_possible_wrap)]
+    let (width, height) = (u32_at(bytes, 18)? as i32, u32_at(bytes, 22)? as i32);
+    let bits_per_pixel = u16_at(bytes, 28)?;
+    let compression = u32_at(bytes, 30)?;
+    let colours_used = u32_at(bytes, 46)? as usize;
+    if width <= 0 || height == 0 {
+        return Err(Error::InvalidImage("invalid bmp size"));
+    }
+    // rows are stored bottom up unless the height is negative
+    let top_down = height < 0;
+    let (width, height) = (width.unsigned_abs(), height.unsigned_abs());
+
+    let masks = match (compression, bits_per_pixel) {
+        (BI_BITFIELDS | BI_ALPHABITFIELDS, 16 | 32) => {
+            let alpha = if compression == BI_ALPHABITFIELDS || header_size >= 56 {
+                u32_at(bytes, 66)?
+            } else {
+                0
+            };
+            [
+                u32_at(bytes, 54)?,
+                u32_at(bytes, 58)?,
+                u32_at(bytes, 62)?,
+                alpha,
+            ]
+        }
+        (BI_RGB, 16) => [0x7c00, 0x03e0, 0x001f, 0],
+        (BI_RGB, 32) => [0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0],
+        (BI_RGB, 1 | 4 | 8 | 24) => [0; 4],
+        _ => return Err(Error::InvalidImage("unsupported bmp compression")),
+    };
+    let masks = masks.map(Mask);
+
+    let palette = if bits_per_pixel <= 8 {
+        let count = if colours_used == 0 {
+            1 << bits_per_pixel
+        } else {
+            colours_used.min(1 << bits_per_pixel)
+        };
+        let start = 14 + header_size;
+        let palette = bytes
+            .get(start..start + count * 4)
+            .ok_or(Error::InvalidImage("truncated bmp"))?;
+        palette
+            .chunks_exact(4)
+            .map(|entry| [entry[2], entry[1], entry[0], u8::MAX])
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let row_length = (width as usize * usize::from(bits_per_pixel)).div_ceil(32) * 4;
+    let data = row_length
+        .checked_mul(height as usize)
+        .and_then(|length| bytes.get(data_offset..data_offset.checked_add(length)?))
+        .ok_or(Error::InvalidImage("truncated bmp"))?;
+    let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height as usize {
+        let row = if top_down { y } else { height as usize - 1 - y };
+        let row = &data[row * row_length..(row + 1) * row_length];
+        for x in 0..width as usize {
+            let pixel = match bits_per_pixel {
+                1 | 4 | 8 => {
+                    let bits = usize::from(bits_per_pixel);
+                    let byte = row[x * bits / 8];
+                    let shift = 8 - bits - x * bits % 8;
+                    let index = usize::from(byte >> shift) & ((1 << bits) - 1);
+                    *palette
+                        .get(index)
+                        .ok_or(Error::InvalidImage("bmp palette index out of range"))?
+                }
+                24 => [row[x * 3 + 2], row[x * 3 + 1], row[x * 3], u8::MAX],
+                _ => {
+                    let pixel = if bits_per_pixel == 16 {
+                        u32::from(u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]))
+                    } else {
+                        u32::from_le_bytes([
+                            row[x * 4],
+                            row[x * 4 + 1],
+                            row[x * 4 + 2],
+                            row[x * 4 + 3],
+                        ])
+                    };
+                    let [red, green, blue, alpha] = masks.map(|mask| mask.extract(pixel));
+                    [
+                        red.unwrap_or(0),
+                        green.unwrap_or(0),
+                        blue.unwrap_or(0),
+                        alpha.unwrap_or(u8::MAX),
+                    ]
+                }
+            };
+            rgba.extend_from_slice(&pixel);
+        }
+    }
+    Ok((width, height, rgba))
+}
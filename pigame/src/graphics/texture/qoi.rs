@@ -0,0 +1,72 @@
+use crate::error::{Error, Result};
+
+const OP_RGB: u8 = 0xfe;
+const OP_RGBA: u8 = 0xff;
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+
+/// Largest image the format allows, in pixels.
+const MAX_PIXELS: u64 = 400_000_000;
+
+/// Decode a QOI image into its size and RGBA bytes.
+pub(super) fn decode(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+    let header = bytes
+        .get(..14)
+        .ok_or(Error::InvalidImage("truncated qoi"))?;
+    let width = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let height = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+    let pixel_count = u64::from(width) * u64::from(height);
+    if pixel_count > MAX_PIXELS {
+        return Err(Error::InvalidImage("qoi image too large"));
+    }
+    // each byte after the header gives at most a run of 62 pixels
+    if pixel_count > (bytes.len() as u64 - 14) * 62 {
+        return Err(Error::InvalidImage("truncated qoi"));
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let length = pixel_count as usize * 4;
+
+    let mut rgba = Vec::with_capacity(length.min((bytes.len() - 14) * 62 * 4));
+    let mut index = [[0_u8; 4]; 64];
+    let mut pixel = [0, 0, 0, u8::MAX];
+    let mut chunks = bytes[14..].iter().copied();
+    let mut next = || chunks.next().ok_or(Error::InvalidImage("truncated qoi"));
+    while rgba.len() < length {
+        let byte = next()?;
+        let mut run = 1;
+        match byte {
+            OP_RGB => pixel = [next()?, next()?, next()?, pixel[3]],
+            OP_RGBA => pixel = [next()?, next()?, next()?, next()?],
+            _ => match byte & 0xc0 {
+                OP_INDEX => pixel = index[usize::from(byte)],
+                OP_DIFF => {
+                    for (channel, shift) in pixel.iter_mut().zip([4, 2, 0]) {
+                        *channel = channel.wrapping_add((byte >> shift) & 3).wrapping_sub(2);
+                    }
+                }
+                OP_LUMA => {
+                    let second = next()?;
+                    let green = (byte & 0x3f).wrapping_sub(32);
+                    pixel[0] = pixel[0]
+                        .wrapping_add(green)
+                        .wrapping_add(second >> 4)
+                        .wrapping_sub(8);
+                    pixel[1] = pixel[1].wrapping_add(green);
+                    pixel[2] = pixel[2]
+                        .wrapping_add(green)
+                        .wrapping_add(second & 0xf)
+                        .wrapping_sub(8);
+                }
+                // run of the previous pixel
+                _ => run = usize::from(byte & 0x3f) + 1,
+            },
+        }
+        let [red, green, blue, alpha] = pixel.map(usize::from);
+        index[(red * 3 + green * 5 + blue * 7 + alpha * 11) % 64] = pixel;
+        for _ in 0..run.min((length - rgba.len()) / 4) {
+            rgba.extend_from_slice(&pixel);
+        }
+    }
+    Ok((width, height, rgba))
+}
@@ -0,0 +1,852 @@
+mod json;
+mod tmx;
+
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+use crate::context::get;
+use crate::error::{Error, Result};
+use crate::maths::Rect;
+
+use super::backend::Region;
+use super::canvas::Canvas;
+use super::colour::Colour;
+use super::texture::{DrawTextureParams, Texture};
+
+/// Bit set in a tile number when the tile is mirrored horizontally, as in Tiled.
+pub const FLIP_X: u32 = 0x8000_0000;
+/// Bit set in a tile number when the tile is mirrored vertically, as in Tiled.
+pub const FLIP_Y: u32 = 0x4000_0000;
+/// Bits Tiled sets for rotated tiles and hexagonal maps, which are ignored when drawing.
+const OTHER_FLAGS: u32 = 0x3000_0000;
+const FLAGS: u32 = FLIP_X | FLIP_Y | OTHER_FLAGS;
+
+/// Image holding tiles of the same size in a grid.
+#[derive(Debug, Clone)]
+pub struct Tileset {
+    texture: Texture,
+    tile_width: u32,
+    tile_height: u32,
+    /// Space around the edges of the image.
+    margin: u32,
+    /// Space between neighbouring tiles.
+    spacing: u32,
+    columns: u32,
+    tile_count: u32,
+}
+
+impl Tileset {
+    /// Split `texture` into tiles of the given size packed edge to edge, numbered from 0 left
+    /// to right then top to bottom.
+    #[must_use]
+    pub fn new(texture: Texture, tile_width: u32, tile_height: u32) -> Self {
+        Self::with_spacing(texture, tile_width, tile_height, 0, 0)
+    }
+
+    /// Split `texture` into tiles of the given size, starting `margin` pixels in from its edges
+    /// and `spacing` pixels apart.
+    #[must_use]
+    pub fn with_spacing(
+        texture: Texture,
+        tile_width: u32,
+        tile_height: u32,
+        margin: u32,
+        spacing: u32,
+    ) -> Self {
+        // in 64 bits so that large margins, spacing and tile sizes cannot overflow
+        #[allow(clippy::cast_possible_truncation)]
+        let count = |size: u32, tile_size: u32| {
+            (u64::from(size).saturating_sub(u64::from(margin) * 2) + u64::from(spacing))
+                .checked_div(u64::from(tile_size) + u64::from(spacing))
+                .map_or(0, |count| count.min(u64::from(size)) as u32)
+        };
+        let columns = count(texture.width(), tile_width);
+        let rows = count(texture.height(), tile_height);
+        Self {
+            texture,
+            tile_width,
+            tile_height,
+            margin,
+            spacing,
+            columns,
+            tile_count: columns.saturating_mul(rows),
+        }
+    }
+
+    /// Get the image the tiles are taken from.
+    #[must_use]
+    pub const fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Get the width of each tile.
+    #[must_use]
+    pub const fn tile_width(&self) -> u32 {
+        self.tile_width
+    }
+
+    /// Get the height of each tile.
+    #[must_use]
+    pub const fn tile_height(&self) -> u32 {
+        self.tile_height
+    }
+
+    /// Get the number of tiles.
+    #[must_use]
+    pub const fn tile_count(&self) -> u32 {
+        self.tile_count
+    }
+
+    /// Get the part of the image showing a tile, or `None` if there is no such tile.
+    #[must_use]
+    pub fn tile(&self, index: u32) -> Option<Region> {
+        (index < self.tile_count).then(|| {
+            let (column, row) = (index % self.columns, index / self.columns);
+            // each part is within the image, so the sums cannot overflow
+            Region::new(
+                self.margin + column * self.tile_width + column * self.spacing,
+                self.margin + row * self.tile_height + row * self.spacing,
+                self.tile_width,
+                self.tile_height,
+            )
+        })
+    }
+}
+
+/// Grid of tile numbers covering a whole tile map.
+///
+/// Tile number 0 is empty, and the rest count through the tiles of each tileset of the map in
+/// turn, starting from 1. [`FLIP_X`] and [`FLIP_Y`] can be added to mirror a tile.
+#[derive(Debug, Clone)]
+pub struct TileLayer {
+    name: String,
+    width: u32,
+    height: u32,
+    tiles: Vec<u32>,
+    /// Whether the layer is drawn by [`draw_tilemap`].
+    pub visible: bool,
+    /// Distance in pixels the layer is drawn from the top left of the map.
+    pub offset: (i32, i32),
+}
+
+impl TileLayer {
+    /// Get the name of the layer.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the tile number at column `x` and row `y`, or `None` if that is outside the layer.
+    #[must_use]
+    pub fn tile(&self, x: u32, y: u32) -> Option<u32> {
+        (x < self.width && y < self.height)
+            .then(|| self.tiles[y as usize * self.width as usize + x as usize])
+    }
+
+    /// Set the tile number at column `x` and row `y`. Does nothing outside the layer.
+    pub fn set_tile(&mut self, x: u32, y: u32, tile: u32) {
+        if x < self.width && y < self.height {
+            self.tiles[y as usize * self.width as usize + x as usize] = tile;
+        }
+    }
+}
+
+/// Shape placed on an object layer of a map made with Tiled, such as a wall or spawn point.
+#[derive(Debug, Clone)]
+pub struct MapObject {
+    /// Number of the object, unique within the map.
+    pub id: u32,
+    /// Name of the object.
+    pub name: String,
+    /// Class (or type) of the object.
+    pub class: String,
+    /// Bounds of the object in pixels from the top left of the map. Points have no size, and
+    /// polygons and polylines are given by their bounding box.
+    pub rect: Rect,
+    /// Custom properties of the object, with their values written as text.
+    pub properties: HashMap<String, String>,
+}
+
+/// Layer of objects placed freely on a map, rather than in the grid.
+#[derive(Debug, Clone)]
+pub struct ObjectLayer {
+    name: String,
+    objects: Vec<MapObject>,
+}
+
+impl ObjectLayer {
+    /// Get the name of the layer.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the objects on the layer.
+    #[must_use]
+    pub fn objects(&self) -> &[MapObject] {
+        &self.objects
+    }
+
+    /// Get the bounds of every object on the layer, such as for collision.
+    pub fn rects(&self) -> impl Iterator<Item = Rect> + '_ {
+        self.objects.iter().map(|object| object.rect)
+    }
+}
+
+/// Map made of layers of tiles from tileset images, such as a level of a platformer.
+#[derive(Debug, Clone)]
+pub struct Tilemap {
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    /// Tilesets in order, with the number of their first tile.
+    tilesets: Vec<(u32, Tileset)>,
+    layers: Vec<TileLayer>,
+    object_layers: Vec<ObjectLayer>,
+}
+
+impl Tilemap {
+    /// Create a map `width` tiles wide and `height` tiles tall with no layers, using tiles from
+    /// `tileset` numbered from 1.
+    #[must_use]
+    pub fn new(width: u32, height: u32, tileset: Tileset) -> Self {
+        Self {
+            width,
+            height,
+            tile_width: tileset.tile_width,
+            tile_height: tileset.tile_height,
+            tilesets: vec![(1, tileset)],
+            layers: Vec::new(),
+            object_layers: Vec::new(),
+        }
+    }
+
+    /// Load an orthogonal map saved by Tiled as TMX or JSON, with its tilesets.
+    ///
+    /// Tile layers, including those in groups, become [`TileLayer`]s in order and object
+    /// layers become [`ObjectLayer`]s. Image layers are skipped, and rotated tiles are drawn
+    /// unrotated.
+    ///
+    /// # Errors
+    ///
+    /// If the map or its tilesets cannot be read, or use features not supported such as
+    /// infinite maps or tilesets made of separate images, an error is returned.
+    pub fn from_tiled_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = read_to_string(path)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        if is_xml(&text) {
+            tmx::load_map(&text, directory)
+        } else {
+            json::load_map(&text, directory)
+        }
+    }
+
+    /// Add another tileset and return the number of its first tile.
+    pub fn add_tileset(&mut self, tileset: Tileset) -> u32 {
+        let first = self.tilesets.last().map_or(1, |(first, tileset)| {
+            first.saturating_add(tileset.tile_count)
+        });
+        self.tilesets.push((first, tileset));
+        first
+    }
+
+    /// Add an empty tile layer on top of the others.
+    pub fn add_layer<S: Into<String>>(&mut self, name: S) -> &mut TileLayer {
+        let length = self.width as usize * self.height as usize;
+        self.layers.push(TileLayer {
+            name: name.into(),
+            width: self.width,
+            height: self.height,
+            tiles: vec![0; length],
+            visible: true,
+            offset: (0, 0),
+        });
+        let last = self.layers.len() - 1;
+        &mut self.layers[last]
+    }
+
+    /// Get the width of the map in tiles.
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the map in tiles.
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the width of each cell of the grid.
+    #[must_use]
+    pub const fn tile_width(&self) -> u32 {
+        self.tile_width
+    }
+
+    /// Get the height of each cell of the grid.
+    #[must_use]
+    pub const fn tile_height(&self) -> u32 {
+        self.tile_height
+    }
+
+    /// Get the tile layers, from bottom to top.
+    #[must_use]
+    pub fn layers(&self) -> &[TileLayer] {
+        &self.layers
+    }
+
+    /// Get the first tile layer called `name`.
+    #[must_use]
+    pub fn layer(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    /// Get the first tile layer called `name`, to change its tiles.
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut TileLayer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    /// Get the object layers.
+    #[must_use]
+    pub fn object_layers(&self) -> &[ObjectLayer] {
+        &self.object_layers
+    }
+
+    /// Get the first object layer called `name`.
+    #[must_use]
+    pub fn object_layer(&self, name: &str) -> Option<&ObjectLayer> {
+        self.object_layers.iter().find(|layer| layer.name == name)
+    }
+
+    /// Get the column and row of the cell containing the point `(x, y)` pixels from the top
+    /// left of the map, or `None` if the point is outside the map.
+    #[must_use]
+    pub fn cell_at(&self, x: i32, y: i32) -> Option<(u32, u32)> {
+        let column = u32::try_from(x).ok()?.checked_div(self.tile_width)?;
+        let row = u32::try_from(y).ok()?.checked_div(self.tile_height)?;
+        (column < self.width && row < self.height).then_some((column, row))
+    }
+
+    /// Add a tile layer loaded from a file.
+    fn push_layer(
+        &mut self,
+        name: String,
+        tiles: Vec<u32>,
+        visible: bool,
+        offset: (i32, i32),
+    ) -> Result<()> {
+        if tiles.len() != self.width as usize * self.height as usize {
+            return Err(Error::InvalidMap("wrong number of tiles for map size"));
+        }
+        self.layers.push(TileLayer {
+            name,
+            width: self.width,
+            height: self.height,
+            tiles,
+            visible,
+            offset,
+        });
+        Ok(())
+    }
+
+    /// Get the tileset holding tile number `tile` and the part of its image showing the tile.
+    fn find_tile(&self, tile: u32) -> Option<(&Tileset, Region)> {
+        let tile = tile & !FLAGS;
+        if tile == 0 {
+            return None;
+        }
+        let (first, tileset) = self
+            .tilesets
+            .iter()
+            .rev()
+            .find(|(first, _)| *first <= tile)?;
+        Some((tileset, tileset.tile(tile - first)?))
+    }
+}
+
+/// Draw the visible tile layers of `tilemap` on the screen from bottom to top, with the point
+/// `(camera_x, camera_y)` pixels from the top left of the map at the top left of the screen.
+pub fn draw_tilemap(tilemap: &Tilemap, camera_x: i32, camera_y: i32) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_tilemap(tilemap, camera_x, camera_y);
+}
+
+/// Draw tile layer number `layer` of `tilemap` on the screen, whether it is visible or not,
+/// with the point `(camera_x, camera_y)` pixels from the top left of the map at the top left of
+/// the screen.
+pub fn draw_tilemap_layer(tilemap: &Tilemap, layer: usize, camera_x: i32, camera_y: i32) {
+    get()
+        .frame_buffer
+        .canvas
+        .draw_tilemap_layer(tilemap, layer, camera_x, camera_y);
+}
+
+impl Canvas {
+    /// Draw the visible tile layers of `tilemap` on the canvas from bottom to top, with the
+    /// point `(camera_x, camera_y)` pixels from the top left of the map at the top left of the
+    /// canvas.
+    pub fn draw_tilemap(&mut self, tilemap: &Tilemap, camera_x: i32, camera_y: i32) {
+        for (index, layer) in tilemap.layers.iter().enumerate() {
+            if layer.visible {
+                self.draw_tilemap_layer(tilemap, index, camera_x, camera_y);
+            }
+        }
+    }
+
+    /// Draw tile layer number `layer` of `tilemap` on the canvas, whether it is visible or not,
+    /// with the point `(camera_x, camera_y)` pixels from the top left of the map at the top left
+    /// of the canvas.
+    ///
+    /// Only tiles at least partly on the canvas are drawn, so large maps cost no more to draw
+    /// than small ones. With a [`Camera2D`](super::camera::Camera2D) set, `camera_x` and
+    /// `camera_y` are usually 0 and the camera scrolls the map instead. Tiles bigger than the
+    /// cells of the map hang over the cells above and to the right, as in Tiled.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn draw_tilemap_layer(
+        &mut self,
+        tilemap: &Tilemap,
+        layer: usize,
+        camera_x: i32,
+        camera_y: i32,
+    ) {
+        let Some(layer) = tilemap.layers.get(layer) else {
+            return;
+        };
+        let (tile_width, tile_height) = (
+            i64::from(tilemap.tile_width),
+            i64::from(tilemap.tile_height),
+        );
+        if tile_width == 0 || tile_height == 0 {
+            return;
+        }
+        let overhang_x = tilemap
+            .tilesets
+            .iter()
+            .map(|(_, tileset)| i64::from(tileset.tile_width) - tile_width)
+            .fold(0, i64::max);
+        let overhang_y = tilemap
+            .tilesets
+            .iter()
+            .map(|(_, tileset)| i64::from(tileset.tile_height) - tile_height)
+            .fold(0, i64::max);
+        // where the top left of the map is drawn
+        let origin_x = i64::from(layer.offset.0) - i64::from(camera_x);
+        let origin_y = i64::from(layer.offset.1) - i64::from(camera_y);
+        let [left, top, right, bottom] = <[i32; 4]>::from(self.visible_bounds()).map(i64::from);
+        let first_column = (left - origin_x - overhang_x).div_euclid(tile_width).max(0);
+        let last_column = (right - origin_x)
+            .div_euclid(tile_width)
+            .min(i64::from(layer.width) - 1);
+        let first_row = (top - origin_y).div_euclid(tile_height).max(0);
+        let last_row = (bottom - origin_y + overhang_y)
+            .div_euclid(tile_height)
+            .min(i64::from(layer.height) - 1);
+        for row in first_row..=last_row {
+            for column in first_column..=last_column {
+                let tile = layer.tiles[(row * i64::from(layer.width) + column) as usize];
+                let Some((tileset, source)) = tilemap.find_tile(tile) else {
+                    continue;
+                };
+                let x = origin_x + column * tile_width;
+                let y = origin_y + (row + 1) * tile_height - i64::from(source.height);
+                let params = DrawTextureParams {
+                    source: Some(source),
+                    flip_x: tile & FLIP_X != 0,
+                    flip_y: tile & FLIP_Y != 0,
+                    ..DrawTextureParams::default()
+                };
+                self.draw_texture_ex(&tileset.texture, x as i32, y as i32, params);
+            }
+        }
+    }
+}
+
+/// Whether the text of a map or tileset file is TMX rather than JSON.
+fn is_xml(text: &str) -> bool {
+    text.trim_start().starts_with('<')
+}
+
+/// Load a tileset saved by Tiled in its own TSX or JSON file.
+fn load_tileset_file(path: &Path) -> Result<Tileset> {
+    let text = read_to_string(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    if is_xml(&text) {
+        tmx::load_tileset(&text, directory)
+    } else {
+        json::load_tileset(&text, directory)
+    }
+}
+
+/// Load the image of a tileset, making pixels of the `transparent` hex colour transparent.
+fn load_tileset_image(
+    image: &Path,
+    tile_width: u32,
+    tile_height: u32,
+    margin: u32,
+    spacing: u32,
+    transparent: Option<&str>,
+) -> Result<Tileset> {
+    let mut texture = Texture::from_file(image)?;
+    if let Some(colour) = transparent.and_then(Colour::from_hex) {
+        texture = texture.with_colour_key(colour);
+    }
+    Ok(Tileset::with_spacing(
+        texture,
+        tile_width,
+        tile_height,
+        margin,
+        spacing,
+    ))
+}
+
+/// Decode base64 tile data, optionally compressed with zlib or gzip, into tile numbers.
+fn decode_tile_data(data: &str, compression: Option<&str>) -> Result<Vec<u32>> {
+    let bytes = decode_base64(data)?;
+    let mut decompressed = Vec::new();
+    let bytes = match compression {
+        None | Some("") => bytes,
+        Some("zlib") => {
+            ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+            decompressed
+        }
+        Some("gzip") => {
+            GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+            decompressed
+        }
+        Some(_) => return Err(Error::InvalidMap("unsupported tile data compression")),
+    };
+    if bytes.len() % 4 != 0 {
+        return Err(Error::InvalidMap("truncated tile data"));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|tile| u32::from_le_bytes([tile[0], tile[1], tile[2], tile[3]]))
+        .collect())
+}
+
+/// Decode base64 text, ignoring whitespace.
+fn decode_base64(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let mut buffer = 0_u32;
+    let mut bits = 0;
+    for character in text
+        .bytes()
+        .filter(|character| !character.is_ascii_whitespace())
+    {
+        let value = match character {
+            b'A'..=b'Z' => character - b'A',
+            b'a'..=b'z' => character - b'a' + 26,
+            b'0'..=b'9' => character - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return Err(Error::InvalidMap("invalid base64 tile data")),
+        };
+        buffer = buffer << 6 | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            #[allow(clippy::cast_possible_truncation)]
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+/// Get the bounds of an object at `(x, y)`. Polygons and polylines have `points` relative to
+/// `(x, y)`, and objects showing a tile are placed by their bottom left corner.
+fn object_rect(x: f32, y: f32, width: f32, height: f32, points: &[(f32, f32)], tile: bool) -> Rect {
+    if points.is_empty() {
+        let y = if tile { y - height } else { y };
+        return Rect::new(x, y, width, height);
+    }
+    let (left, top, right, bottom) = points.iter().fold(
+        (
+            f32::INFINITY,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NEG_INFINITY,
+        ),
+        |(left, top, right, bottom), &(point_x, point_y)| {
+            (
+                left.min(point_x),
+                top.min(point_y),
+                right.max(point_x),
+                bottom.max(point_y),
+            )
+        },
+    );
+    Rect::new(x + left, y + top, right - left, bottom - top)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, write};
+    use std::path::PathBuf;
+    use std::process::id;
+
+    use super::super::colour::{BLACK, BLUE, GREEN, RED};
+    use super::super::testing::screen;
+    use super::super::{clear_background, get_pixel};
+    use super::*;
+
+    /// Tiles 2 by 1 pixels: red and blue, then green.
+    const TILES: &[u8] = &[
+        b'q', b'o', b'i', b'f', 0, 0, 0, 4, 0, 0, 0, 1, 4, 0, //
+        0xff, 255, 0, 0, 255, //
+        0xfe, 0, 0, 255, //
+        0xfe, 0, 255, 0,    //
+        0xc0, //
+        0, 0, 0, 0, 0, 0, 0, 1,
+    ];
+
+    /// A single blue tile 2 by 1 pixels.
+    const MORE_TILES: &[u8] = &[
+        b'q', b'o', b'i', b'f', 0, 0, 0, 2, 0, 0, 0, 1, 4, 0, //
+        0xfe, 0, 0, 255,  //
+        0xc0, //
+        0, 0, 0, 0, 0, 0, 0, 1,
+    ];
+
+    const MORE_TILES_TSX: &str = r#"<tileset name="more" tilewidth="2" tileheight="1">
+        <image source="more.qoi" width="2" height="1"/>
+    </tileset>"#;
+
+    /// Tiles 1 flipped across, 2, 3 and none, as little-endian bytes in base64.
+    const BASE64: &str = "AQAAgAIAAAADAAAAAAAAAA==";
+    const ZLIB: &str = "eJxjZGBoYGJgYGBmgAAABtAAhw==";
+    const GZIP: &str = "H4sIAAAAAAACA2NkYGhgYmBgYGaAAACvZg2AEAAAAA==";
+
+    /// Directory holding the tileset images and files.
+    fn directory() -> PathBuf {
+        let directory = temp_dir().join(format!("pigame-{}-tilemap", id()));
+        create_dir_all(&directory).expect("the directory can be created");
+        for (name, bytes) in [
+            ("tiles.qoi", TILES),
+            ("more.qoi", MORE_TILES),
+            ("more.tsx", MORE_TILES_TSX.as_bytes()),
+        ] {
+            write(directory.join(name), bytes).expect("the file can be written");
+        }
+        directory
+    }
+
+    /// TMX map 2 by 2 cells of 2 by 1 pixels, with a tile layer holding `data`.
+    fn tmx(data: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <map orientation="orthogonal" width="2" height="2" tilewidth="2" tileheight="1">
+                <tileset firstgid="1" name="tiles" tilewidth="2" tileheight="1">
+                    <image source="tiles.qoi" width="4" height="1"/>
+                </tileset>
+                <tileset firstgid="3" source="more.tsx"/>
+                <layer name="ground" width="2" height="2">{data}</layer>
+            </map>"#
+        )
+    }
+
+    /// JSON map 2 by 2 cells of 2 by 1 pixels, with a tile layer described by `layer`.
+    fn json(layer: &str) -> String {
+        format!(
+            r#"{{
+                "orientation": "orthogonal", "width": 2, "height": 2,
+                "tilewidth": 2, "tileheight": 1,
+                "tilesets": [
+                    {{ "firstgid": 1, "image": "tiles.qoi", "tilewidth": 2, "tileheight": 1 }},
+                    {{ "firstgid": 3, "source": "more.tsx" }}
+                ],
+                "layers": [{{ "type": "tilelayer", "name": "ground", {layer} }}]
+            }}"#
+        )
+    }
+
+    /// Check that `tilemap` holds tile 1 flipped across, 2, 3 and none, and draws them.
+    fn assert_ground(tilemap: &Tilemap) {
+        let ground = tilemap.layer("ground").expect("the map has a ground layer");
+        assert_eq!(ground.tile(0, 0), Some(1 | FLIP_X));
+        assert_eq!(ground.tile(1, 0), Some(2));
+        assert_eq!(ground.tile(0, 1), Some(3));
+        assert_eq!(ground.tile(1, 1), Some(0));
+        clear_background(BLACK);
+        draw_tilemap(tilemap, 0, 0);
+        let pixels = [
+            [BLUE, RED, GREEN, GREEN, BLACK],
+            [BLUE, BLUE, BLACK, BLACK, BLACK],
+        ];
+        for (y, row) in (0..).zip(pixels) {
+            for (x, colour) in (0..).zip(row) {
+                assert_eq!(get_pixel(x, y), Some(colour), "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn tmx_maps_load_tiles_in_every_encoding() {
+        let _screen = screen();
+        let directory = directory();
+        let csv = r#"<data encoding="csv">
+            2147483649,2,
+            3,0
+        </data>"#;
+        let xml = r#"<data>
+            <tile gid="2147483649"/><tile gid="2"/><tile gid="3"/><tile/>
+        </data>"#;
+        let base64 = format!(r#"<data encoding="base64">{BASE64}</data>"#);
+        let zlib = format!(r#"<data encoding="base64" compression="zlib">{ZLIB}</data>"#);
+        let gzip = format!(r#"<data encoding="base64" compression="gzip">{GZIP}</data>"#);
+        for data in [csv, xml, &base64, &zlib, &gzip] {
+            let tilemap = tmx::load_map(&tmx(data), &directory).expect("the map is valid");
+            assert_ground(&tilemap);
+        }
+    }
+
+    #[test]
+    fn json_maps_load_tiles_in_every_encoding() {
+        let _screen = screen();
+        let directory = directory();
+        let array = r#""data": [2147483649, 2, 3, 0]"#.to_owned();
+        let base64 = format!(r#""encoding": "base64", "data": "{BASE64}""#);
+        let zlib = format!(r#""encoding": "base64", "compression": "zlib", "data": "{ZLIB}""#);
+        let gzip = format!(r#""encoding": "base64", "compression": "gzip", "data": "{GZIP}""#);
+        for layer in [array, base64, zlib, gzip] {
+            let tilemap = json::load_map(&json(&layer), &directory).expect("the map is valid");
+            assert_ground(&tilemap);
+        }
+    }
+
+    #[test]
+    fn tiles_are_found_in_the_tileset_they_belong_to() {
+        let _screen = screen();
+        let data = r#"<data encoding="csv">1,2,3,4</data>"#;
+        let tilemap = tmx::load_map(&tmx(data), &directory()).expect("the map is valid");
+        let tile = |number| {
+            tilemap
+                .find_tile(number)
+                .map(|(tileset, region)| (tileset.texture().width(), region))
+        };
+        assert_eq!(tile(0), None);
+        assert_eq!(tile(1), Some((4, Region::new(0, 0, 2, 1))));
+        assert_eq!(tile(2 | FLIP_Y), Some((4, Region::new(2, 0, 2, 1))));
+        assert_eq!(tile(3), Some((2, Region::new(0, 0, 2, 1))));
+        assert_eq!(tile(4), None);
+    }
+
+    #[test]
+    fn layers_and_objects_are_moved_by_their_offsets() {
+        let _screen = screen();
+        let text = r#"<map width="2" height="2" tilewidth="2" tileheight="1">
+            <group name="level" offsetx="4" offsety="1">
+                <layer name="ground" offsetx="-1" offsety="2">
+                    <data encoding="csv">0,0,0,0</data>
+                </layer>
+                <objectgroup name="things" offsety="-1">
+                    <object id="7" name="door" type="exit" x="1" y="2" width="3" height="4">
+                        <properties><property name="to" value="cellar"/></properties>
+                    </object>
+                    <object id="8" x="10" y="10">
+                        <polygon points="0,0 -2,1 3,5"/>
+                    </object>
+                </objectgroup>
+            </group>
+        </map>"#;
+        let tilemap = tmx::load_map(text, &directory()).expect("the map is valid");
+        assert_eq!(tilemap.layers()[0].offset, (3, 3));
+        let things = tilemap.object_layer("things").expect("the map has objects");
+        let door = &things.objects()[0];
+        assert_eq!((door.id, door.name.as_str()), (7, "door"));
+        assert_eq!(door.class, "exit");
+        assert_eq!(
+            door.properties.get("to").map(String::as_str),
+            Some("cellar")
+        );
+        let rects: Vec<_> = things
+            .rects()
+            .map(|rect| (rect.x, rect.y, rect.w, rect.h))
+            .collect();
+        assert_eq!(rects, [(5., 2., 3., 4.), (12., 10., 5., 5.)]);
+
+        let text = r#"{ "width": 2, "height": 2, "tilewidth": 2, "tileheight": 1, "layers": [
+            { "type": "group", "offsetx": 4, "layers": [
+                { "type": "tilelayer", "offsety": 2, "data": [0, 0, 0, 0] },
+                { "type": "objectgroup", "name": "things", "objects": [
+                    { "id": 1, "x": 1, "y": 2, "width": 3, "height": 4, "gid": 1 }
+                ] }
+            ] }
+        ] }"#;
+        let tilemap = json::load_map(text, &directory()).expect("the map is valid");
+        assert_eq!(tilemap.layers()[0].offset, (4, 2));
+        let things = tilemap.object_layer("things").expect("the map has objects");
+        let rects: Vec<_> = things
+            .rects()
+            .map(|rect| (rect.x, rect.y, rect.w, rect.h))
+            .collect();
+        // objects showing tiles are placed by their bottom left corner
+        assert_eq!(rects, [(5., -2., 3., 4.)]);
+    }
+
+    #[test]
+    fn invalid_tile_data_is_refused() {
+        let _screen = screen();
+        let directory = directory();
+        for data in [
+            r#"<data encoding="base64">AQAA*AIA</data>"#,
+            r#"<data encoding="hex">01000000</data>"#,
+            r#"<data encoding="base64" compression="zstd">AQAAgA==</data>"#,
+            r#"<data encoding="base64">AQAAgAI=</data>"#,
+            r#"<data encoding="csv">1,2,3</data>"#,
+            r#"<data encoding="csv">1,2,3,x</data>"#,
+        ] {
+            assert!(
+                matches!(
+                    tmx::load_map(&tmx(data), &directory),
+                    Err(Error::InvalidMap(_))
+                ),
+                "{data}"
+            );
+        }
+        for layer in [
+            r#""data": [1, 2, 3]"#,
+            r#""data": "AQAA*AIA""#,
+            r#""data": [-1, 0, 0, 0]"#,
+        ] {
+            assert!(
+                matches!(
+                    json::load_map(&json(layer), &directory),
+                    Err(Error::InvalidMap(_))
+                ),
+                "{layer}"
+            );
+        }
+    }
+
+    #[test]
+    fn decode_base64_ignores_whitespace_and_stops_at_padding() {
+        assert_eq!(decode_base64("TWFu").expect("valid"), b"Man");
+        assert_eq!(decode_base64(" TW\nE= ").expect("valid"), b"Ma");
+        assert_eq!(decode_base64("TQ==").expect("valid"), b"M");
+        assert_eq!(decode_base64("").expect("valid"), b"");
+        assert!(decode_base64("TW-u").is_err());
+    }
+
+    #[test]
+    fn tilesets_with_huge_spacing_have_only_the_tiles_that_fit() {
+        let _screen = screen();
+        let texture = Texture::from_bytes(TILES).expect("the image is valid");
+        let tileset = Tileset::with_spacing(texture.clone(), u32::MAX, u32::MAX, 0, u32::MAX);
+        assert_eq!(tileset.tile_count(), 0);
+        let tileset = Tileset::with_spacing(texture.clone(), 1, 1, u32::MAX, u32::MAX);
+        assert_eq!(tileset.tile_count(), 0);
+        let tileset = Tileset::with_spacing(texture, 1, 1, 0, u32::MAX);
+        assert_eq!(tileset.tile_count(), 1);
+        assert_eq!(tileset.tile(0), Some(Region::new(0, 0, 1, 1)));
+    }
+}
@@ -0,0 +1,179 @@
+use std::path::Path;
+
+use serde_json::{from_str, Value};
+
+use crate::error::{Error, Result};
+
+use super::{
+    decode_tile_data, load_tileset_file, load_tileset_image, object_rect, MapObject, ObjectLayer,
+    Tilemap, Tileset,
+};
+
+fn get_u32(value: &Value, key: &str) -> Option<u32> {
+    u32::try_from(value.get(key)?.as_u64()?).ok()
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn get_f32(value: &Value, key: &str) -> Option<f32> {
+    value.get(key)?.as_f64().map(|number| number as f32)
+}
+
+fn get_str<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key)?.as_str()
+}
+
+fn get_array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .map_or(&[], Vec::as_slice)
+}
+
+/// Load a map saved by Tiled as JSON, with tilesets relative to `directory`.
+pub(super) fn load_map(text: &str, directory: &Path) -> Result<Tilemap> {
+    let map: Value = from_str(text)?;
+    if get_str(&map, "orientation").is_some_and(|orientation| orientation != "orthogonal") {
+        return Err(Error::InvalidMap("only orthogonal maps are supported"));
+    }
+    if map.get("infinite").and_then(Value::as_bool) == Some(true) {
+        return Err(Error::InvalidMap("infinite maps are not supported"));
+    }
+    let size = get_u32(&map, "width").zip(get_u32(&map, "height"));
+    let tile_size = get_u32(&map, "tilewidth").zip(get_u32(&map, "tileheight"));
+    let ((width, height), (tile_width, tile_height)) = size
+        .zip(tile_size)
+        .ok_or(Error::InvalidMap("map has no size"))?;
+
+    let tilesets = get_array(&map, "tilesets")
+        .iter()
+        .map(|tileset| {
+            let first = get_u32(tileset, "firstgid")
+                .ok_or(Error::InvalidMap("tileset has no first tile number"))?;
+            let tileset = match get_str(tileset, "source") {
+                Some(source) => load_tileset_file(&directory.join(source))?,
+                None => parse_tileset(tileset, directory)?,
+            };
+            Ok((first, tileset))
+        })
+        .collect::<Result<_>>()?;
+    let mut tilemap = Tilemap {
+        width,
+        height,
+        tile_width,
+        tile_height,
+        tilesets,
+        layers: Vec::new(),
+        object_layers: Vec::new(),
+    };
+    add_layers(&mut tilemap, get_array(&map, "layers"), (0, 0), true)?;
+    Ok(tilemap)
+}
+
+/// Load a tileset saved by Tiled as JSON, with its image relative to `directory`.
+pub(super) fn load_tileset(text: &str, directory: &Path) -> Result<Tileset> {
+    parse_tileset(&from_str(text)?, directory)
+}
+
+fn parse_tileset(tileset: &Value, directory: &Path) -> Result<Tileset> {
+    let image = get_str(tileset, "image").ok_or(Error::InvalidMap(
+        "tilesets made of separate images are not supported",
+    ))?;
+    let (tile_width, tile_height) = get_u32(tileset, "tilewidth")
+        .zip(get_u32(tileset, "tileheight"))
+        .ok_or(Error::InvalidMap("tileset has no tile size"))?;
+    load_tileset_image(
+        &directory.join(image),
+        tile_width,
+        tile_height,
+        get_u32(tileset, "margin").unwrap_or(0),
+        get_u32(tileset, "spacing").unwrap_or(0),
+        get_str(tileset, "transparentcolor"),
+    )
+}
+
+/// Add tile and object layers to `tilemap`, including those inside groups, offset from the top
+/// left of the map by `offset` and hidden unless `visible`.
+#[allow(clippy::cast_possible_truncation)]
+fn add_layers(
+    tilemap: &mut Tilemap,
+    layers: &[Value],
+    offset: (i32, i32),
+    visible: bool,
+) -> Result<()> {
+    for layer in layers {
+        let name = get_str(layer, "name").unwrap_or_default().to_owned();
+        let (x, y) = (get_f32(layer, "offsetx"), get_f32(layer, "offsety"));
+        let offset = (
+            offset.0.saturating_add(x.unwrap_or(0.) as i32),
+            offset.1.saturating_add(y.unwrap_or(0.) as i32),
+        );
+        let visible = visible && layer.get("visible").and_then(Value::as_bool) != Some(false);
+        match get_str(layer, "type") {
+            Some("tilelayer") => {
+                let tiles = match layer.get("data") {
+                    Some(Value::String(data)) => {
+                        decode_tile_data(data, get_str(layer, "compression"))?
+                    }
+                    Some(Value::Array(data)) => data
+                        .iter()
+                        .map(|tile| u32::try_from(tile.as_u64()?).ok())
+                        .collect::<Option<_>>()
+                        .ok_or(Error::InvalidMap("invalid tile number"))?,
+                    _ => return Err(Error::InvalidMap("tile layer has no data")),
+                };
+                tilemap.push_layer(name, tiles, visible, offset)?;
+            }
+            Some("objectgroup") => {
+                let objects = get_array(layer, "objects")
+                    .iter()
+                    .map(|object| parse_object(object, offset))
+                    .collect();
+                tilemap.object_layers.push(ObjectLayer { name, objects });
+            }
+            Some("group") => add_layers(tilemap, get_array(layer, "layers"), offset, visible)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn parse_object(object: &Value, offset: (i32, i32)) -> MapObject {
+    let points: Vec<_> = get_array(object, "polygon")
+        .iter()
+        .chain(get_array(object, "polyline"))
+        .map(|point| {
+            (
+                get_f32(point, "x").unwrap_or(0.),
+                get_f32(point, "y").unwrap_or(0.),
+            )
+        })
+        .collect();
+    let properties = get_array(object, "properties")
+        .iter()
+        .filter_map(|property| {
+            let value = match property.get("value")? {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            Some((get_str(property, "name")?.to_owned(), value))
+        })
+        .collect();
+    MapObject {
+        id: get_u32(object, "id").unwrap_or(0),
+        name: get_str(object, "name").unwrap_or_default().to_owned(),
+        class: get_str(object, "class")
+            .or_else(|| get_str(object, "type"))
+            .unwrap_or_default()
+            .to_owned(),
+        rect: object_rect(
+            get_f32(object, "x").unwrap_or(0.) + offset.0 as f32,
+            get_f32(object, "y").unwrap_or(0.) + offset.1 as f32,
+            get_f32(object, "width").unwrap_or(0.),
+            get_f32(object, "height").unwrap_or(0.),
+            &points,
+            object.get("gid").is_some(),
+        ),
+        properties,
+    }
+}
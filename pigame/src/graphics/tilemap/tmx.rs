@@ -0,0 +1,193 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use roxmltree::{Document, Node};
+
+use crate::error::{Error, Result};
+
+use super::{
+    decode_tile_data, load_tileset_file, load_tileset_image, object_rect, MapObject, ObjectLayer,
+    Tilemap, Tileset,
+};
+
+fn attribute<T: FromStr>(node: Node<'_, '_>, name: &str) -> Option<T> {
+    node.attribute(name)?.parse().ok()
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+/// Load a map saved by Tiled as TMX, with tilesets relative to `directory`.
+pub(super) fn load_map(text: &str, directory: &Path) -> Result<Tilemap> {
+    let document = Document::parse(text)?;
+    let map = document.root_element();
+    if !map.has_tag_name("map") {
+        return Err(Error::InvalidMap("not a tiled map"));
+    }
+    if map
+        .attribute("orientation")
+        .is_some_and(|orientation| orientation != "orthogonal")
+    {
+        return Err(Error::InvalidMap("only orthogonal maps are supported"));
+    }
+    if map.attribute("infinite") == Some("1") {
+        return Err(Error::InvalidMap("infinite maps are not supported"));
+    }
+    let size = attribute(map, "width").zip(attribute(map, "height"));
+    let tile_size = attribute(map, "tilewidth").zip(attribute(map, "tileheight"));
+    let ((width, height), (tile_width, tile_height)) = size
+        .zip(tile_size)
+        .ok_or(Error::InvalidMap("map has no size"))?;
+
+    let tilesets = map
+        .children()
+        .filter(|node| node.has_tag_name("tileset"))
+        .map(|tileset| {
+            let first = attribute(tileset, "firstgid")
+                .ok_or(Error::InvalidMap("tileset has no first tile number"))?;
+            let tileset = match tileset.attribute("source") {
+                Some(source) => load_tileset_file(&directory.join(source))?,
+                None => parse_tileset(tileset, directory)?,
+            };
+            Ok((first, tileset))
+        })
+        .collect::<Result<_>>()?;
+    let mut tilemap = Tilemap {
+        width,
+        height,
+        tile_width,
+        tile_height,
+        tilesets,
+        layers: Vec::new(),
+        object_layers: Vec::new(),
+    };
+    add_layers(&mut tilemap, map, (0, 0), true)?;
+    Ok(tilemap)
+}
+
+/// Load a tileset saved by Tiled as TSX, with its image relative to `directory`.
+pub(super) fn load_tileset(text: &str, directory: &Path) -> Result<Tileset> {
+    parse_tileset(Document::parse(text)?.root_element(), directory)
+}
+
+fn parse_tileset(tileset: Node<'_, '_>, directory: &Path) -> Result<Tileset> {
+    let image = child(tileset, "image");
+    let source = image
+        .and_then(|image| image.attribute("source"))
+        .ok_or(Error::InvalidMap(
+            "tilesets made of separate images are not supported",
+        ))?;
+    let (tile_width, tile_height) = attribute(tileset, "tilewidth")
+        .zip(attribute(tileset, "tileheight"))
+        .ok_or(Error::InvalidMap("tileset has no tile size"))?;
+    load_tileset_image(
+        &directory.join(source),
+        tile_width,
+        tile_height,
+        attribute(tileset, "margin").unwrap_or(0),
+        attribute(tileset, "spacing").unwrap_or(0),
+        image.and_then(|image| image.attribute("trans")),
+    )
+}
+
+/// Add the tile and object layers in `parent` to `tilemap`, including those inside groups,
+/// offset from the top left of the map by `offset` and hidden unless `visible`.
+#[allow(clippy::cast_possible_truncation)]
+fn add_layers(
+    tilemap: &mut Tilemap,
+    parent: Node<'_, '_>,
+    offset: (i32, i32),
+    visible: bool,
+) -> Result<()> {
+    for layer in parent.children().filter(Node::is_element) {
+        let name = layer.attribute("name").unwrap_or_default().to_owned();
+        let (x, y) = (
+            attribute::<f32>(layer, "offsetx"),
+            attribute::<f32>(layer, "offsety"),
+        );
+        let offset = (
+            offset.0.saturating_add(x.unwrap_or(0.) as i32),
+            offset.1.saturating_add(y.unwrap_or(0.) as i32),
+        );
+        let visible = visible && layer.attribute("visible") != Some("0");
+        match layer.tag_name().name() {
+            "layer" => {
+                let data =
+                    child(layer, "data").ok_or(Error::InvalidMap("tile layer has no data"))?;
+                let text = data.text().unwrap_or_default();
+                let tiles = match data.attribute("encoding") {
+                    None => data
+                        .children()
+                        .filter(|node| node.has_tag_name("tile"))
+                        .map(|tile| attribute(tile, "gid").unwrap_or(0))
+                        .collect(),
+                    Some("csv") => text
+                        .split(',')
+                        .map(|tile| tile.trim().parse().ok())
+                        .collect::<Option<_>>()
+                        .ok_or(Error::InvalidMap("invalid tile number"))?,
+                    Some("base64") => decode_tile_data(text, data.attribute("compression"))?,
+                    Some(_) => return Err(Error::InvalidMap("unsupported tile data encoding")),
+                };
+                tilemap.push_layer(name, tiles, visible, offset)?;
+            }
+            "objectgroup" => {
+                let objects = layer
+                    .children()
+                    .filter(|node| node.has_tag_name("object"))
+                    .map(|object| parse_object(object, offset))
+                    .collect();
+                tilemap.object_layers.push(ObjectLayer { name, objects });
+            }
+            "group" => add_layers(tilemap, layer, offset, visible)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn parse_object(object: Node<'_, '_>, offset: (i32, i32)) -> MapObject {
+    let points: Vec<_> = child(object, "polygon")
+        .or_else(|| child(object, "polyline"))
+        .and_then(|shape| shape.attribute("points"))
+        .map(|points| {
+            points
+                .split_whitespace()
+                .filter_map(|point| {
+                    let (x, y) = point.split_once(',')?;
+                    Some((x.parse().ok()?, y.parse().ok()?))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let properties = child(object, "properties")
+        .into_iter()
+        .flat_map(|properties| properties.children())
+        .filter(|node| node.has_tag_name("property"))
+        .filter_map(|property| {
+            // long text values are written inside the element instead of as an attribute
+            let value = property.attribute("value").or_else(|| property.text())?;
+            Some((property.attribute("name")?.to_owned(), value.to_owned()))
+        })
+        .collect();
+    MapObject {
+        id: attribute(object, "id").unwrap_or(0),
+        name: object.attribute("name").unwrap_or_default().to_owned(),
+        class: object
+            .attribute("class")
+            .or_else(|| object.attribute("type"))
+            .unwrap_or_default()
+            .to_owned(),
+        rect: object_rect(
+            attribute::<f32>(object, "x").unwrap_or(0.) + offset.0 as f32,
+            attribute::<f32>(object, "y").unwrap_or(0.) + offset.1 as f32,
+            attribute(object, "width").unwrap_or(0.),
+            attribute(object, "height").unwrap_or(0.),
+            &points,
+            object.attribute("gid").is_some(),
+        ),
+        properties,
+    }
+}
@@ -0,0 +1,68 @@
+use super::backend::Region;
+use super::Rotation;
+
+/// Where `region` of a `width` by `height` frame ends up after [`transform_into`].
+pub(super) const fn transform_region(
+    region: Region,
+    (width, height): (u32, u32),
+    rotation: Rotation,
+    (flip_horizontal, flip_vertical): (bool, bool),
+) -> Region {
+    let (mut left, mut right) = (region.x, region.right());
+    let (mut top, mut bottom) = (region.y, region.bottom());
+    if flip_horizontal {
+        (left, right) = (width - right, width - left);
+    }
+    if flip_vertical {
+        (top, bottom) = (height - bottom, height - top);
+    }
+    let (x, y, right, bottom) = match rotation {
+        Rotation::None => (left, top, right, bottom),
+        Rotation::Clockwise90 => (height - bottom, left, height - top, right),
+        Rotation::Rotate180 => (width - right, height - bottom, width - left, height - top),
+        Rotation::Clockwise270 => (top, width - right, bottom, width - left),
+    };
+    Region::new(x, y, right - x, bottom - y)
+}
+
+/// Copy `source` into `region` of `destination`, mirroring it and then rotating it clockwise.
+///
+/// Sizes are `(width, height, stride)`; the destination must be the source size after rotation.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn transform_into(
+    source: &[u8],
+    (source_width, source_height, source_stride): (u32, u32, usize),
+    destination: &mut [u8],
+    stride: usize,
+    bytes_per_pixel: usize,
+    rotation: Rotation,
+    (flip_horizontal, flip_vertical): (bool, bool),
+    region: Region,
+) {
+    let (source_width, source_height) = (source_width as usize, source_height as usize);
+    for y in region.y as usize..region.bottom() as usize {
+        for x in region.x as usize..region.right() as usize {
+            // find the source pixel that ends up at (x, y)
+            let (source_x, source_y) = match rotation {
+                Rotation::None => (x, y),
+                Rotation::Clockwise90 => (y, source_height - 1 - x),
+                Rotation::Rotate180 => (source_width - 1 - x, source_height - 1 - y),
+                Rotation::Clockwise270 => (source_width - 1 - y, x),
+            };
+            let source_x = if flip_horizontal {
+                source_width - 1 - source_x
+            } else {
+                source_x
+            };
+            let source_y = if flip_vertical {
+                source_height - 1 - source_y
+            } else {
+                source_y
+            };
+            let source_start = source_y * source_stride + source_x * bytes_per_pixel;
+            let start = y * stride + x * bytes_per_pixel;
+            destination[start..start + bytes_per_pixel]
+                .copy_from_slice(&source[source_start..source_start + bytes_per_pixel]);
+        }
+    }
+}
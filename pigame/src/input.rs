@@ -1,3 +1,4 @@
+use crate::context::try_get;
 use crate::error::Result;
 use rppal::gpio::Gpio;
 use std::ops::Index;
@@ -6,16 +7,34 @@ use strum::VariantArray;
 
 /// Return true if the input is active.
 ///
+/// If the display backend reads input itself (like
+/// [`TerminalBackend`](crate::graphics::backend::TerminalBackend)), its state is used instead of
+/// the GPIO pins.
+///
 /// # Errors
 ///
 /// If the GPIO pin cannot be accessed, an error is returned.
 pub fn is_active(input: Input) -> Result<bool> {
+    if let Some(active) =
+        try_get().and_then(|context| context.frame_buffer.backend.is_input_active(input))
+    {
+        return Ok(active);
+    }
     Ok(Gpio::new()?
         .get(Input::GPIO_MAP[input as usize])?
         .into_input_pulldown()
         .is_high())
 }
 
+/// Return true if the player asked the game to quit through the display backend.
+///
+/// Pressing Ctrl-C with [`TerminalBackend`](crate::graphics::backend::TerminalBackend) asks to
+/// quit. Games should exit when they see it, as the backend does not end the program itself.
+#[must_use]
+pub fn is_quit_requested() -> bool {
+    try_get().is_some_and(|context| context.frame_buffer.backend.is_quit_requested())
+}
+
 macro_rules! impl_input {
     ($($name:ident => $pin:expr,)*) => {
         /// Return the first active input.